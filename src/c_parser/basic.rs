@@ -3,6 +3,10 @@
 //! The C standard referenced is found
 //! [here](http://www.open-std.org/jtc1/sc22/wg14/www/abq/c17_updated_proposed_fdis.pdf).
 
+// The doc comments on the nom parsers are not attached to the generated functions and `count_fixed!`
+// uses a deprecated function internally.
+#![allow(unused_doc_comments, deprecated)]
+
 /// Parses a `nondigit` according to the C standard.
 named!(pub nondigit,
     recognize!(
@@ -82,7 +86,392 @@ named!(pub identifier,
     )
 );
 
+/// Parses a `nonzero-digit` according to the C standard.
+named!(pub nonzero_digit,
+    recognize!(
+        one_of!("123456789")
+    )
+);
+
+/// Parses an `octal-digit` according to the C standard.
+named!(pub octal_digit,
+    recognize!(
+        one_of!("01234567")
+    )
+);
+
+/// Parses a `digit-sequence` according to the C standard.
+named!(pub digit_sequence,
+    recognize!(
+        many1!(
+            digit
+        )
+    )
+);
+
+/// Parses a `hexadecimal-digit-sequence` according to the C standard.
+named!(pub hexadecimal_digit_sequence,
+    recognize!(
+        many1!(
+            hexadecimal_digit
+        )
+    )
+);
+
+/// Parses a `hexadecimal-prefix` according to the C standard.
+named!(pub hexadecimal_prefix,
+    alt!(
+        tag!("0x") |
+        tag!("0X")
+    )
+);
+
+/// Parses a `decimal-constant` according to the C standard.
+named!(pub decimal_constant,
+    recognize!(
+        pair!(
+            nonzero_digit,
+            many0!(
+                digit
+            )
+        )
+    )
+);
+
+/// Parses an `octal-constant` according to the C standard.
+named!(pub octal_constant,
+    recognize!(
+        pair!(
+            tag!("0"),
+            many0!(
+                octal_digit
+            )
+        )
+    )
+);
+
+/// Parses a `hexadecimal-constant` according to the C standard.
+named!(pub hexadecimal_constant,
+    recognize!(
+        pair!(
+            hexadecimal_prefix,
+            hexadecimal_digit_sequence
+        )
+    )
+);
+
+/// Parses an `unsigned-suffix` according to the C standard.
+named!(pub unsigned_suffix,
+    recognize!(
+        one_of!("uU")
+    )
+);
+
+/// Parses a `long-suffix` according to the C standard.
+named!(pub long_suffix,
+    recognize!(
+        one_of!("lL")
+    )
+);
+
+/// Parses a `long-long-suffix` according to the C standard.
+named!(pub long_long_suffix,
+    alt!(
+        tag!("ll") |
+        tag!("LL")
+    )
+);
+
+/// Parses an `integer-suffix` according to the C standard.
+named!(pub integer_suffix,
+    alt!(
+        recognize!(
+            pair!(
+                unsigned_suffix,
+                long_long_suffix
+            )
+        ) |
+        recognize!(
+            pair!(
+                unsigned_suffix,
+                long_suffix
+            )
+        ) |
+        unsigned_suffix |
+        recognize!(
+            pair!(
+                long_long_suffix,
+                unsigned_suffix
+            )
+        ) |
+        long_long_suffix |
+        recognize!(
+            pair!(
+                long_suffix,
+                unsigned_suffix
+            )
+        ) |
+        long_suffix
+    )
+);
+
+/// Parses an `integer-constant` according to the C standard.
+named!(pub integer_constant,
+    recognize!(
+        pair!(
+            alt!(
+                hexadecimal_constant |
+                octal_constant |
+                decimal_constant
+            ),
+            opt!(
+                integer_suffix
+            )
+        )
+    )
+);
+
+/// Parses a `sign` according to the C standard.
+named!(pub sign,
+    recognize!(
+        one_of!("+-")
+    )
+);
+
+/// Parses a `fractional-constant` according to the C standard.
+named!(pub fractional_constant,
+    alt!(
+        recognize!(
+            tuple!(
+                opt!(
+                    digit_sequence
+                ),
+                char!('.'),
+                digit_sequence
+            )
+        ) |
+        recognize!(
+            pair!(
+                digit_sequence,
+                char!('.')
+            )
+        )
+    )
+);
+
+/// Parses an `exponent-part` according to the C standard.
+named!(pub exponent_part,
+    recognize!(
+        tuple!(
+            one_of!("eE"),
+            opt!(
+                sign
+            ),
+            digit_sequence
+        )
+    )
+);
+
+/// Parses a `floating-suffix` according to the C standard.
+named!(pub floating_suffix,
+    recognize!(
+        one_of!("flFL")
+    )
+);
+
+/// Parses a `decimal-floating-constant` according to the C standard.
+named!(pub decimal_floating_constant,
+    alt!(
+        recognize!(
+            tuple!(
+                fractional_constant,
+                opt!(
+                    exponent_part
+                ),
+                opt!(
+                    floating_suffix
+                )
+            )
+        ) |
+        recognize!(
+            tuple!(
+                digit_sequence,
+                exponent_part,
+                opt!(
+                    floating_suffix
+                )
+            )
+        )
+    )
+);
+
+/// Parses a `hexadecimal-fractional-constant` according to the C standard.
+named!(pub hexadecimal_fractional_constant,
+    alt!(
+        recognize!(
+            tuple!(
+                opt!(
+                    hexadecimal_digit_sequence
+                ),
+                char!('.'),
+                hexadecimal_digit_sequence
+            )
+        ) |
+        recognize!(
+            pair!(
+                hexadecimal_digit_sequence,
+                char!('.')
+            )
+        )
+    )
+);
+
+/// Parses a `binary-exponent-part` according to the C standard.
+named!(pub binary_exponent_part,
+    recognize!(
+        tuple!(
+            one_of!("pP"),
+            opt!(
+                sign
+            ),
+            digit_sequence
+        )
+    )
+);
+
+/// Parses a `hexadecimal-floating-constant` according to the C standard.
+named!(pub hexadecimal_floating_constant,
+    recognize!(
+        tuple!(
+            hexadecimal_prefix,
+            alt!(
+                hexadecimal_fractional_constant |
+                hexadecimal_digit_sequence
+            ),
+            binary_exponent_part,
+            opt!(
+                floating_suffix
+            )
+        )
+    )
+);
+
+/// Parses a `floating-constant` according to the C standard.
+named!(pub floating_constant,
+    alt!(
+        hexadecimal_floating_constant |
+        decimal_floating_constant
+    )
+);
+
+/// Parses a `simple-escape-sequence` according to the C standard.
+named!(pub simple_escape_sequence,
+    recognize!(
+        pair!(
+            char!('\\'),
+            one_of!("'\"?\\abfnrtv")
+        )
+    )
+);
+
+/// Parses an `octal-escape-sequence` according to the C standard.
+named!(pub octal_escape_sequence,
+    recognize!(
+        pair!(
+            char!('\\'),
+            many_m_n!(
+                1,
+                3,
+                octal_digit
+            )
+        )
+    )
+);
+
+/// Parses a `hexadecimal-escape-sequence` according to the C standard.
+named!(pub hexadecimal_escape_sequence,
+    recognize!(
+        pair!(
+            tag!("\\x"),
+            hexadecimal_digit_sequence
+        )
+    )
+);
+
+/// Parses an `escape-sequence` according to the C standard.
+named!(pub escape_sequence,
+    alt!(
+        simple_escape_sequence |
+        octal_escape_sequence |
+        hexadecimal_escape_sequence |
+        universal_character_name
+    )
+);
+
+/// Parses a `c-char` according to the C standard.
+named!(pub c_char,
+    alt!(
+        recognize!(
+            none_of!("'\\\n")
+        ) |
+        escape_sequence
+    )
+);
+
+/// Parses a `character-constant` according to the C standard.
+named!(pub character_constant,
+    recognize!(
+        tuple!(
+            opt!(
+                one_of!("LuU")
+            ),
+            char!('\''),
+            many1!(
+                c_char
+            ),
+            char!('\'')
+        )
+    )
+);
+
+/// Parses an `encoding-prefix` according to the C standard.
+named!(pub encoding_prefix,
+    alt!(
+        tag!("u8") |
+        tag!("u") |
+        tag!("U") |
+        tag!("L")
+    )
+);
+
+/// Parses an `s-char` according to the C standard.
+named!(pub s_char,
+    alt!(
+        recognize!(
+            none_of!("\"\\\n")
+        ) |
+        escape_sequence
+    )
+);
+
+/// Parses a `string-literal` according to the C standard.
+named!(pub string_literal,
+    recognize!(
+        tuple!(
+            opt!(
+                encoding_prefix
+            ),
+            char!('"'),
+            many0!(
+                s_char
+            ),
+            char!('"')
+        )
+    )
+);
+
 #[cfg(test)]
+#[allow(clippy::byte_char_slices)]
 mod tests {
     use super::*;
     use nom;
@@ -253,4 +642,191 @@ mod tests {
         );
         assert!(identifier(b"") == Err(nom::Err::Incomplete(nom::Needed::Size(1))));
     }
+
+    #[test]
+    fn test_integer_suffix() {
+        assert!(integer_suffix(b"u ") == Ok((&[b' '], &[b'u'])));
+        assert!(integer_suffix(b"L ") == Ok((&[b' '], &[b'L'])));
+        assert!(integer_suffix(b"ul ") == Ok((&[b' '], &[b'u', b'l'])));
+        assert!(integer_suffix(b"Ull ") == Ok((&[b' '], &[b'U', b'l', b'l'])));
+        assert!(integer_suffix(b"LLu ") == Ok((&[b' '], &[b'L', b'L', b'u'])));
+        assert!(integer_suffix(b"lU ") == Ok((&[b' '], &[b'l', b'U'])));
+        assert!(integer_suffix(b"lL ") == Ok((&[b'L', b' '], &[b'l'])));
+        assert!(
+            integer_suffix(b"x") == Err(nom::Err::Error(nom::Context::Code(
+                &[b'x'],
+                nom::ErrorKind::Alt
+            )))
+        );
+        assert!(integer_suffix(b"") == Err(nom::Err::Incomplete(nom::Needed::Size(1))));
+    }
+
+    #[test]
+    fn test_integer_constant() {
+        assert!(integer_constant(b"42 ") == Ok((&[b' '], &[b'4', b'2'])));
+        assert!(integer_constant(b"42u;") == Ok((&[b';'], &[b'4', b'2', b'u'])));
+        assert!(
+            integer_constant(b"0x1f ") == Ok((&[b' '], &[b'0', b'x', b'1', b'f']))
+        );
+        assert!(
+            integer_constant(b"0XABCllu,")
+                == Ok((&[b','], &[b'0', b'X', b'A', b'B', b'C', b'l', b'l', b'u']))
+        );
+        assert!(
+            integer_constant(b"0777l)") == Ok((&[b')'], &[b'0', b'7', b'7', b'7', b'l']))
+        );
+        assert!(integer_constant(b"0 ") == Ok((&[b' '], &[b'0'])));
+        assert!(integer_constant(b"089") == Ok((&[b'8', b'9'], &[b'0'])));
+        assert!(integer_constant(b"3.14") == Ok((&[b'.', b'1', b'4'], &[b'3'])));
+        assert!(
+            integer_constant(b"a") == Err(nom::Err::Error(nom::Context::Code(
+                &[b'a'],
+                nom::ErrorKind::Alt
+            )))
+        );
+        assert!(integer_constant(b"1234") == Err(nom::Err::Incomplete(nom::Needed::Size(1))));
+        assert!(integer_constant(b"") == Err(nom::Err::Incomplete(nom::Needed::Size(2))));
+    }
+
+    #[test]
+    fn test_exponent_part() {
+        assert!(exponent_part(b"e10 ") == Ok((&[b' '], &[b'e', b'1', b'0'])));
+        assert!(exponent_part(b"E-3 ") == Ok((&[b' '], &[b'E', b'-', b'3'])));
+        assert!(exponent_part(b"e+7f") == Ok((&[b'f'], &[b'e', b'+', b'7'])));
+        assert!(
+            exponent_part(b"e ") == Err(nom::Err::Error(nom::Context::Code(
+                &[b' '],
+                nom::ErrorKind::Many1
+            )))
+        );
+        assert!(exponent_part(b"") == Err(nom::Err::Incomplete(nom::Needed::Size(1))));
+    }
+
+    #[test]
+    fn test_floating_constant() {
+        assert!(floating_constant(b"3.14 ") == Ok((&[b' '], &[b'3', b'.', b'1', b'4'])));
+        assert!(floating_constant(b"1e10 ") == Ok((&[b' '], &[b'1', b'e', b'1', b'0'])));
+        assert!(floating_constant(b".5f,") == Ok((&[b','], &[b'.', b'5', b'f'])));
+        assert!(floating_constant(b"3. ") == Ok((&[b' '], &[b'3', b'.'])));
+        assert!(
+            floating_constant(b"1.5e-3L)")
+                == Ok((&[b')'], &[b'1', b'.', b'5', b'e', b'-', b'3', b'L']))
+        );
+        assert!(
+            floating_constant(b"0x1.8p3f ")
+                == Ok((&[b' '], &[b'0', b'x', b'1', b'.', b'8', b'p', b'3', b'f']))
+        );
+        assert!(
+            floating_constant(b"0x1p-2 ") == Ok((&[b' '], &[b'0', b'x', b'1', b'p', b'-', b'2']))
+        );
+        assert!(
+            floating_constant(b"42 ") == Err(nom::Err::Error(nom::Context::Code(
+                &[b'4', b'2', b' '],
+                nom::ErrorKind::Alt
+            )))
+        );
+        assert!(
+            floating_constant(b"0x1.8 ") == Err(nom::Err::Error(nom::Context::Code(
+                &[b'0', b'x', b'1', b'.', b'8', b' '],
+                nom::ErrorKind::Alt
+            )))
+        );
+        assert!(floating_constant(b"3.14") == Err(nom::Err::Incomplete(nom::Needed::Size(1))));
+        assert!(floating_constant(b"") == Err(nom::Err::Incomplete(nom::Needed::Size(2))));
+    }
+
+    #[test]
+    fn test_escape_sequence() {
+        assert!(escape_sequence(b"\\n") == Ok((&[], &[b'\\', b'n'])));
+        assert!(escape_sequence(b"\\\\") == Ok((&[], &[b'\\', b'\\'])));
+        assert!(escape_sequence(b"\\\"") == Ok((&[], &[b'\\', b'"'])));
+        assert!(escape_sequence(b"\\t ") == Ok((&[b' '], &[b'\\', b't'])));
+        assert!(escape_sequence(b"\\0'") == Ok((&[b'\''], &[b'\\', b'0'])));
+        assert!(escape_sequence(b"\\1234") == Ok((&[b'4'], &[b'\\', b'1', b'2', b'3'])));
+        assert!(escape_sequence(b"\\x1f'") == Ok((&[b'\''], &[b'\\', b'x', b'1', b'f'])));
+        assert!(
+            escape_sequence(b"\\u1337") == Ok((&[], &[b'\\', b'u', b'1', b'3', b'3', b'7']))
+        );
+        assert!(
+            escape_sequence(b"\\q") == Err(nom::Err::Error(nom::Context::Code(
+                &[b'\\', b'q'],
+                nom::ErrorKind::Alt
+            )))
+        );
+        assert!(
+            escape_sequence(b"n") == Err(nom::Err::Error(nom::Context::Code(
+                &[b'n'],
+                nom::ErrorKind::Alt
+            )))
+        );
+        assert!(escape_sequence(b"") == Err(nom::Err::Incomplete(nom::Needed::Size(1))));
+    }
+
+    #[test]
+    fn test_character_constant() {
+        assert!(character_constant(b"'x' ") == Ok((&[b' '], &[b'\'', b'x', b'\''])));
+        assert!(
+            character_constant(b"'\\n')") == Ok((&[b')'], &[b'\'', b'\\', b'n', b'\'']))
+        );
+        assert!(
+            character_constant(b"'\\''") == Ok((&[], &[b'\'', b'\\', b'\'', b'\'']))
+        );
+        assert!(
+            character_constant(b"L'ab';") == Ok((&[b';'], &[b'L', b'\'', b'a', b'b', b'\'']))
+        );
+        assert!(
+            character_constant(b"'\\u00e4' ")
+                == Ok((&[b' '], &[b'\'', b'\\', b'u', b'0', b'0', b'e', b'4', b'\'']))
+        );
+        assert!(
+            character_constant(b"'' ") == Err(nom::Err::Error(nom::Context::Code(
+                &[b'\'', b' '],
+                nom::ErrorKind::Many1
+            )))
+        );
+        assert!(
+            character_constant(b"x") == Err(nom::Err::Error(nom::Context::Code(
+                &[b'x'],
+                nom::ErrorKind::Char
+            )))
+        );
+        assert!(character_constant(b"'x") == Err(nom::Err::Incomplete(nom::Needed::Size(1))));
+    }
+
+    #[test]
+    fn test_string_literal() {
+        assert!(
+            string_literal(b"\"abc\\n\" ")
+                == Ok((&[b' '], &[b'"', b'a', b'b', b'c', b'\\', b'n', b'"']))
+        );
+        assert!(string_literal(b"\"\";") == Ok((&[b';'], &[b'"', b'"'])));
+        assert!(
+            string_literal(b"\"a\\\"b\",")
+                == Ok((&[b','], &[b'"', b'a', b'\\', b'"', b'b', b'"']))
+        );
+        assert!(
+            string_literal(b"L\"\\t\\\\\" ")
+                == Ok((&[b' '], &[b'L', b'"', b'\\', b't', b'\\', b'\\', b'"']))
+        );
+        assert!(
+            string_literal(b"u8\"x\" ") == Ok((&[b' '], &[b'u', b'8', b'"', b'x', b'"']))
+        );
+        assert!(
+            string_literal(b"\"\\u1234\"")
+                == Ok((&[], &[b'"', b'\\', b'u', b'1', b'2', b'3', b'4', b'"']))
+        );
+        assert!(
+            string_literal(b"\"\\z\" ") == Err(nom::Err::Error(nom::Context::Code(
+                &[b'\\', b'z', b'"', b' '],
+                nom::ErrorKind::Char
+            )))
+        );
+        assert!(
+            string_literal(b"abc") == Err(nom::Err::Error(nom::Context::Code(
+                &[b'a', b'b', b'c'],
+                nom::ErrorKind::Char
+            )))
+        );
+        assert!(string_literal(b"\"abc") == Err(nom::Err::Incomplete(nom::Needed::Size(1))));
+    }
 }
@@ -1,251 +1,2523 @@
 //! This module parses C Code.
 
-use self::basic::identifier;
+// The doc comments on the nom parsers are not attached to the generated functions.
+#![allow(unused_doc_comments)]
+
+use self::basic::{digit, identifier, identifier_nondigit};
 use super::Config;
+use diagnostics::Diagnostics;
 use nom::{self, multispace};
-use std::{fmt, fmt::Write, str::from_utf8};
+use std::{collections::HashMap, fmt, fmt::Write, io, slice, str::from_utf8};
 
 mod basic;
 
-/// Transforms a file from the source form to its final form.
-pub fn transform_file(content: &[u8], config: &Config) -> Result<String, String> {
-    let mut functions = Vec::new();
-    let mut input = &content[..];
-    let mut output = String::new();
+/// An error in the input or while generating the code for it.
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    /// The message describing the error.
+    pub message: String,
+    /// The line and column in the input the error was found at, if it is known.
+    pub location: Option<(usize, usize)>,
+}
 
-    loop {
-        match function(&input) {
-            Ok((new_input, result)) => {
-                input = new_input;
-                functions.push(result);
-            }
-            Err(nom::Err::Incomplete(_)) => break,
-            Err(e) => {
-                Err(format!("Parser error: {:?}", e))?;
-            }
+impl ParseError {
+    /// Creates an error found at the start of the given rest of the content.
+    fn at(message: String, content: &[u8], rest: &[u8]) -> ParseError {
+        // Whitespace in front of the erroneous item is not part of it.
+        let offset = content.len() - rest.len()
+            + rest.iter().take_while(|byte| byte.is_ascii_whitespace()).count();
+        let line_start = content[..offset]
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |newline| newline + 1);
+        let line = content[..offset].iter().filter(|&&byte| byte == b'\n').count() + 1;
+
+        ParseError {
+            message,
+            location: Some((line, offset - line_start + 1)),
         }
     }
+}
 
-    output.push_str("#define _GNU_SOURCE\n");
-    output.push_str("#include<dlfcn.h>\n");
-    output.push_str("#include<stdio.h>\n");
-
-    for function in functions {
-        output.push_str("\n");
-        function
-            .get_definition(&mut output, &config.debug_output)
-            .map_err(|err| format!("Error writing tranformed file: {}", err))?;
+impl From<String> for ParseError {
+    fn from(message: String) -> ParseError {
+        ParseError {
+            message,
+            location: None,
+        }
     }
-
-    Ok(output)
 }
 
-/// Represents a C type qualifier.
-#[derive(Debug)]
-enum TypeQualifier {
-    /// The const type qualifier.
-    Const,
-    /// The restrict type qualifier.
-    Restrict,
-    /// The volatile type qualifier.
-    Volatile,
-    /// The _Atomic type qualifier.
-    Atomic,
+impl From<&str> for ParseError {
+    fn from(message: &str) -> ParseError {
+        message.to_string().into()
+    }
 }
 
-impl fmt::Display for TypeQualifier {
+impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            TypeQualifier::Const => write!(f, "const"),
-            TypeQualifier::Restrict => write!(f, "restrict"),
-            TypeQualifier::Volatile => write!(f, "volatile"),
-            TypeQualifier::Atomic => write!(f, "_Atomic"),
-        }
+        write!(f, "{}", self.message)
     }
 }
 
-/// Represents a type in C.
+/// Transforms a file from the source form to its final form.
+pub fn transform_file(
+    content: &[u8],
+    config: &Config,
+    diagnostics: &mut Diagnostics,
+) -> Result<String, ParseError> {
+    let mut output = Vec::new();
+    transform_file_to(content, config, diagnostics, &mut output)?;
+
+    Ok(String::from_utf8(output).expect("generated code is valid UTF-8"))
+}
+
+/// Transforms a file from the source form to its final form, writing it to the given writer.
 ///
-/// # Note
-/// This does not yet correspond to the C standard and just supports a subset of possible types.
-#[derive(Debug)]
-struct Type {
-    /// The qualifiers used in this type.
-    qualifiers: Vec<TypeQualifier>,
-    /// The specifier used for this type.
-    specifier: String,
-    /// The amount of pointer indirections on this type.
-    pointer: usize,
+/// The definition of each function is written as soon as it is generated. Returns the number of
+/// generated stubs.
+pub fn transform_file_to<W: io::Write>(
+    content: &[u8],
+    config: &Config,
+    diagnostics: &mut Diagnostics,
+    writer: &mut W,
+) -> Result<usize, ParseError> {
+    let functions = parse_functions(content, config, diagnostics)?;
+    write_functions(&functions, config, writer)?;
+
+    Ok(functions.len())
 }
 
-impl fmt::Display for Type {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for qualifier in &self.qualifiers {
-            write!(f, "{} ", qualifier)?;
+/// Transforms a file from the source form to one final form per function.
+///
+/// The result contains the name of each function together with its code.
+pub fn transform_file_split(
+    content: &[u8],
+    config: &Config,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<(String, String)>, ParseError> {
+    let functions = parse_functions(content, config, diagnostics)?;
+
+    functions
+        .iter()
+        .map(|function| {
+            let mut output = Vec::new();
+            write_functions(slice::from_ref(function), config, &mut output)?;
+
+            Ok((
+                function.name.clone(),
+                String::from_utf8(output).expect("generated code is valid UTF-8"),
+            ))
+        }).collect()
+}
+
+/// Writes the code for the given functions, including everything they need.
+fn write_functions<W: io::Write>(
+    functions: &[Function],
+    config: &Config,
+    writer: &mut W,
+) -> Result<(), ParseError> {
+    let mut output = String::new();
+
+    if config.cxx && !config.no_header_defines {
+        // C++ compilers usually define it already.
+        output.push_str("#ifndef _GNU_SOURCE\n#define _GNU_SOURCE\n#endif\n");
+    } else if !config.no_header_defines {
+        output.push_str("#define _GNU_SOURCE\n");
+    }
+    let mut headers = vec!["dlfcn.h", "stdio.h"];
+
+    if config.pointer_format == PointerFormat::Hex {
+        headers.push("stdint.h");
+    }
+
+    if functions.iter().any(|function| !function.return_type.is_void()) {
+        headers.push("stdlib.h");
+    }
+
+    for (keyword, header) in TYPE_HEADERS {
+        if functions.iter().any(|function| function.uses_specifier(keyword)) {
+            headers.push(header);
         }
+    }
 
-        write!(f, "{}", self.specifier)?;
+    if !config.failures.is_empty() {
+        headers.push("errno.h");
+    }
 
-        if self.pointer > 0 {
-            write!(f, " ")?;
+    if functions.iter().any(|function| function.variadic) {
+        headers.push("stdarg.h");
+    }
+
+    if !config.fail_conditions.is_empty() {
+        headers.push("string.h");
+    }
+
+    if !config.fail_rates.is_empty() {
+        headers.push("stdlib.h");
+        headers.push("time.h");
+    }
+
+    if config.log_banner || !config.fail_rates.is_empty() {
+        headers.push("unistd.h");
+    }
+
+    if let Some(template) = &config.template {
+        if template.contains(&TemplatePart::Tid) {
+            headers.push("sys/syscall.h");
         }
 
-        for _ in 0..self.pointer {
-            write!(f, "*")?;
+        if template.contains(&TemplatePart::Pid) || template.contains(&TemplatePart::Tid) {
+            headers.push("unistd.h");
         }
+    }
 
-        Ok(())
+    if config.log_format == LogFormat::Binary {
+        headers.push("fcntl.h");
+        headers.push("stdint.h");
+        headers.push("string.h");
+        headers.push("unistd.h");
     }
-}
 
-impl Type {
-    /// Returns a format specifier for this type.
-    fn get_format_specifier(&self) -> &'static str {
-        match (&self.specifier[..], self.pointer) {
-            ("char", 1) => "\\\"%s\\\"",
-            ("int", 0) => "%d",
-            ("size_t", 0) => "%zd",
-            (_, 0) => "{Unknown Type: %d}",
-            (_, _) => "%p",
+    if config.record.is_some() {
+        headers.push("fcntl.h");
+        headers.push("stdint.h");
+        headers.push("sys/uio.h");
+        headers.push("unistd.h");
+    }
+
+    if config.per_thread_log.is_some() {
+        headers.push("limits.h");
+        headers.push("sys/stat.h");
+        headers.push("sys/syscall.h");
+        headers.push("unistd.h");
+    }
+
+    if config.backtrace.is_some() {
+        headers.push("execinfo.h");
+        headers.push("unistd.h");
+    }
+
+    let logs_raw = functions.iter().any(|function| function.logs_raw(config));
+    if logs_raw {
+        headers.push("fcntl.h");
+        headers.push("stdarg.h");
+        headers.push("sys/syscall.h");
+        headers.push("unistd.h");
+    }
+
+    if config.canonical {
+        headers.sort_unstable();
+    }
+
+    for (i, header) in headers.iter().enumerate() {
+        if !headers[..i].contains(header) {
+            output.push_str(&format!("#include<{}>\n", header));
         }
     }
 
-    /// Returns true, if this type is the void type.
-    fn is_void(&self) -> bool {
-        match (&self.specifier[..], self.pointer) {
-            ("void", 0) => true,
-            _ => false,
+    if let Some(hook_header) = &config.hook_header {
+        output.push_str(&format!(
+            "#include \"{}\"\n",
+            escape_string_literal(hook_header)
+        ));
+    }
+
+    // Everything after the headers is declared with C linkage, including the hooks.
+    if config.cxx {
+        output.push_str("\nextern \"C\" {\n");
+    }
+
+    if config.hook_header.is_some() {
+        output.push('\n');
+
+        for function in functions {
+            function
+                .write_hook_declaration(&mut output)
+                .map_err(|err| format!("Error writing tranformed file: {}", err))?;
         }
     }
-}
 
-/// Represents a C function.
-#[derive(Debug)]
-struct Function {
-    /// The return type of the function.
-    return_type: Type,
-    /// The name identifying the function.
-    name: String,
-    /// The parameters of the function.
-    parameters: Vec<(Type, String)>,
+    if config.log_format == LogFormat::Binary {
+        output.push('\n');
+        write_store_word(&mut output)
+            .map_err(|err| format!("Error writing tranformed file: {}", err))?;
+    }
+
+    if logs_raw {
+        output.push('\n');
+        write_raw_log(&mut output)
+            .map_err(|err| format!("Error writing tranformed file: {}", err))?;
+    }
+
+    if let Some(dir) = &config.per_thread_log {
+        output.push('\n');
+        write_thread_output(&mut output, dir)
+            .map_err(|err| format!("Error writing tranformed file: {}", err))?;
+    }
+
+    if config.log_banner {
+        output.push('\n');
+        write_banner(&mut output, config)
+            .map_err(|err| format!("Error writing tranformed file: {}", err))?;
+    }
+
+    if logs_sequence(config) {
+        output.push_str("\nstatic unsigned long ldpsc_sequence;\n");
+    }
+
+    if !config.fail_rates.is_empty() {
+        output.push('\n');
+        write_seed(&mut output).map_err(|err| format!("Error writing tranformed file: {}", err))?;
+    }
+
+    writer
+        .write_all(output.as_bytes())
+        .map_err(|err| format!("Error writing tranformed file: {}", err))?;
+
+    for function in functions {
+        output.clear();
+        output.push('\n');
+        function
+            .get_definition(&mut output, config)
+            .map_err(|err| format!("Error writing tranformed file: {}", err))?;
+        writer
+            .write_all(output.as_bytes())
+            .map_err(|err| format!("Error writing tranformed file: {}", err))?;
+    }
+
+    if config.cxx {
+        writer
+            .write_all(b"\n}\n")
+            .map_err(|err| format!("Error writing tranformed file: {}", err))?;
+    }
+
+    Ok(())
 }
 
-impl Function {
-    /// Writes the signature of this function. Optionally as a function pointer.
-    fn get_signature(&self, f: &mut Write, as_pointer: bool) -> fmt::Result {
-        if self.return_type.pointer > 0 {
-            write!(f, "{}", self.return_type)?;
-        } else {
-            write!(f, "{} ", self.return_type)?;
-        }
+/// Lists the functions in a file with their number of parameters and their return type.
+pub fn list_functions(
+    content: &[u8],
+    config: &Config,
+    diagnostics: &mut Diagnostics,
+) -> Result<String, ParseError> {
+    let mut output = String::new();
 
-        if as_pointer {
-            write!(f, "(*original_{})(", self.name)?;
-        } else {
-            write!(f, "{}(", self.name)?;
-        }
+    for function in parse_functions(content, config, diagnostics)? {
+        writeln!(
+            output,
+            "{}\t{}\t{}",
+            function.name,
+            function.parameters.len(),
+            function.return_type
+        ).map_err(|err| format!("Error writing function list: {}", err))?;
+    }
 
-        for (i, parameter) in self.parameters.iter().enumerate() {
-            write!(f, "{}", parameter.0)?;
+    Ok(output)
+}
 
-            if parameter.0.pointer == 0 {
-                write!(f, " ")?;
-            }
+/// Returns the names of the functions in a file.
+pub fn function_names(
+    content: &[u8],
+    config: &Config,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<String>, ParseError> {
+    Ok(parse_functions(content, config, diagnostics)?
+        .into_iter()
+        .map(|function| function.name)
+        .collect())
+}
 
-            write!(f, "{}", parameter.1)?;
+/// Returns the mapping of the function ids used in binary logs to the functions in a file.
+pub fn binary_log_schema(
+    content: &[u8],
+    config: &Config,
+    diagnostics: &mut Diagnostics,
+) -> Result<String, ParseError> {
+    let mut output = String::new();
 
-            if i != self.parameters.len() - 1 {
-                write!(f, ", ")?;
-            }
-        }
+    for function in parse_functions(content, config, diagnostics)? {
+        let parameter_types: Vec<_> = function
+            .parameters
+            .iter()
+            .map(|parameter| parameter.0.to_string())
+            .collect();
 
-        write!(f, ")")
+        writeln!(
+            output,
+            "{}\t{}\t{}\t{}",
+            function.id,
+            function.name,
+            function.return_type,
+            parameter_types.join("\t")
+        ).map_err(|err| format!("Error writing schema: {}", err))?;
     }
 
-    /// Writes the definition of this function.
-    fn get_definition(&self, f: &mut Write, output: &str) -> fmt::Result {
-        let keep_result = !self.return_type.is_void();
+    Ok(output)
+}
 
-        self.get_signature(f, false)?;
-        write!(f, " {{\n")?;
+/// Parses the function prototypes in a file and checks that stubs can be generated for them.
+fn parse_functions(
+    content: &[u8],
+    config: &Config,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Function>, ParseError> {
+    let mut functions = Vec::new();
+    let mut input = content;
+    let mut skipped = 0;
+    let mut library = None;
 
-        if output == "-" {
-            write!(f, "    FILE *output = stderr;\n")?;
-        } else {
-            write!(f, "    FILE *output = fopen(\"{}\", \"a\");\n", output)?;
+    loop {
+        // A comment can annotate the library of the next function.
+        if let Ok((rest, comment)) = line_comment(input) {
+            input = rest;
+            library = library_annotation(comment).or(library);
+            continue;
         }
 
-        write!(f, "    ")?;
-        self.get_signature(f, true)?;
-        write!(f, " = dlsym(RTLD_NEXT, \"{}\");\n", self.name)?;
+        let parsed = function(input);
 
-        write!(f, "    ")?;
+        if parsed.is_err() && !config.lenient {
+            if let Some(name) = missing_semicolon(input) {
+                let message = format!("Expected ';' after function declaration of '{}'", name);
+                Err(ParseError::at(message, content, input))?;
+            }
 
-        if keep_result {
-            if self.return_type.pointer > 0 {
-                write!(f, "{}result = ", self.return_type)?;
-            } else {
-                write!(f, "{} result = ", self.return_type)?;
+            if let Ok((_, name)) = function_pointer_return(input) {
+                let message = format!(
+                    "Function pointer return types are not supported, found in the declaration of '{}'",
+                    name
+                );
+                Err(ParseError::at(message, content, input))?;
+            }
+
+            if let Some((keyword, rest)) = typeof_keyword(input) {
+                let message = format!(
+                    "'{}' is not supported, replace it with the type it refers to",
+                    keyword
+                );
+                Err(ParseError::at(message, content, rest))?;
             }
         }
 
-        write!(f, "original_{}(", self.name)?;
+        match parsed {
+            Ok((new_input, mut result)) => {
+                result.source = from_utf8(&input[..input.len() - new_input.len()])
+                    .map(|source| source.trim().to_string())
+                    .unwrap_or_default();
+                input = new_input;
+                result.library = library.take();
+                functions.push(result);
+            }
+            Err(nom::Err::Incomplete(_)) => break,
+            Err(_) if config.lenient => {
+                let rest = skip_item(input);
+                let item = String::from_utf8_lossy(&input[..input.len() - rest.len()]);
 
-        for (i, parameter) in self.parameters.iter().enumerate() {
-            write!(f, "{}", parameter.1)?;
+                diagnostics.verbose(format_args!(
+                    "skipping '{}'",
+                    item.split_whitespace().collect::<Vec<_>>().join(" ")
+                ));
+                skipped += 1;
 
-            if i != self.parameters.len() - 1 {
-                write!(f, ", ")?;
+                input = rest;
+                if input.iter().all(u8::is_ascii_whitespace) {
+                    break;
+                }
+            }
+            Err(e) => {
+                Err(ParseError::at(format!("Parser error: {:?}", e), content, input))?;
             }
         }
+    }
 
-        write!(f, ");\n")?;
+    if skipped > 0 {
+        diagnostics.warning(format_args!("skipped {} unrecognized item(s)", skipped));
+    }
+
+    if functions.is_empty() {
+        Err("No function prototypes found in input")?;
+    }
 
-        write!(f, "    fprintf(output, \"")?;
+    if let Some(selected) = &config.selected_functions {
+        functions.retain(|function| selected.contains(&function.name));
 
-        if keep_result {
-            write!(f, "{} = ", self.return_type.get_format_specifier())?;
+        if functions.is_empty() {
+            Err("No functions were selected")?;
         }
-        write!(f, "{}(", self.name)?;
+    }
 
-        for (i, parameter) in self.parameters.iter().enumerate() {
-            write!(f, "{}", parameter.0.get_format_specifier())?;
+    check_parameter_names(&functions)?;
+    check_variadic(&functions)?;
+    check_buffer_lengths(&functions)?;
+    let mut functions = remove_duplicates(functions, diagnostics)?;
+    check_interposable(&functions, config, diagnostics)?;
+    check_prototypes(&functions, config, diagnostics)?;
+    find_va_list_variants(&mut functions, config)?;
 
-            if i != self.parameters.len() - 1 {
-                write!(f, ", ")?;
-            }
-        }
+    if config.cxx {
+        adapt_to_cxx(&mut functions)?;
+    }
+    check_failures(&functions, config)?;
 
-        write!(f, ")\\n\"")?;
+    if config.canonical {
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+    }
 
-        if keep_result {
-            write!(f, ", result")?;
+    for (id, function) in functions.iter_mut().enumerate() {
+        function.id = id;
+    }
+
+    diagnostics.verbose(format_args!("parsed {} function(s)", functions.len()));
+
+    Ok(functions)
+}
+
+/// Returns the name of the function, if the input starts with a declaration missing its `;`.
+fn missing_semicolon(input: &[u8]) -> Option<String> {
+    match function_declarator(input) {
+        Ok((rest, function)) if function_terminator(rest).is_err() => Some(function.name),
+        _ => None,
+    }
+}
+
+/// The spellings of the GNU `typeof` operator.
+const TYPEOF_KEYWORDS: &[&str] = &["typeof", "__typeof", "__typeof__"];
+
+/// Returns the `typeof` keyword used in the next top level item and the input starting at it.
+fn typeof_keyword(input: &[u8]) -> Option<(&'static str, &[u8])> {
+    let item = &input[..input.len() - skip_item(input).len()];
+    let is_identifier_byte = |byte: &u8| byte.is_ascii_alphanumeric() || *byte == b'_';
+
+    for start in 0..item.len() {
+        if start > 0 && is_identifier_byte(&item[start - 1]) {
+            continue;
         }
 
-        for parameter in &self.parameters {
-            write!(f, ", {}", parameter.1)?;
+        let length = item[start..].iter().take_while(|byte| is_identifier_byte(byte)).count();
+        let word = &item[start..start + length];
+
+        if let Some(keyword) = TYPEOF_KEYWORDS.iter().find(|keyword| keyword.as_bytes() == word) {
+            return Some((keyword, &input[start..]));
         }
+    }
+
+    None
+}
+
+/// Returns the library a comment of the form `lib: NAME` annotates.
+///
+/// The `.so` extension is added to the library if it has none.
+fn library_annotation(comment: &[u8]) -> Option<String> {
+    let comment = from_utf8(comment).ok()?.trim();
+    let library = comment.strip_prefix("lib:")?.trim();
+
+    if library.is_empty() {
+        None
+    } else if library.contains(".so") {
+        Some(library.to_string())
+    } else {
+        Some(format!("{}.so", library))
+    }
+}
+
+/// Returns the format annotation in the text of a comment, if it contains one.
+fn format_annotation(comment: &[u8]) -> Option<FormatAnnotation> {
+    match from_utf8(comment).ok()?.trim() {
+        "dec" => Some(FormatAnnotation::Decimal),
+        "hex" => Some(FormatAnnotation::Hex),
+        "string" => Some(FormatAnnotation::String),
+        "pointer" => Some(FormatAnnotation::Pointer),
+        _ => None,
+    }
+}
+
+/// Returns the parameter a comment of the form `length: NAME` names as the length of a buffer.
+fn length_annotation(comment: &[u8]) -> Option<String> {
+    let comment = from_utf8(comment).ok()?.trim();
+    let length = comment.strip_prefix("length:")?.trim();
+
+    if length.is_empty() {
+        None
+    } else {
+        Some(length.to_string())
+    }
+}
+
+/// Returns the input after the next top level item.
+///
+/// An item ends with a `;` or the `}` closing its outermost block.
+fn skip_item(input: &[u8]) -> &[u8] {
+    let mut depth = 0usize;
 
-        write!(f, ");\n")?;
+    for (i, byte) in input.iter().enumerate() {
+        match byte {
+            b';' if depth == 0 => return &input[i + 1..],
+            b'{' => depth += 1,
+            b'}' => {
+                depth = depth.saturating_sub(1);
 
-        if output != "-" {
-            write!(f, "    fclose(output);\n")?;
+                if depth == 0 {
+                    return &input[i + 1..];
+                }
+            }
+            _ => (),
         }
+    }
 
-        if keep_result {
-            write!(f, "    return result;\n")?;
+    &input[input.len()..]
+}
+
+/// The format used to log pointers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerFormat {
+    /// The implementation defined format of `%p`.
+    Native,
+    /// A zero padded hexadecimal number, which is the same on every system.
+    Hex,
+}
+
+/// The radix used to log integers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntRadix {
+    /// Decimal numbers, which are signed for signed types.
+    Decimal,
+    /// Hexadecimal numbers prefixed with `0x`, which is useful for flags.
+    Hex,
+}
+
+/// The point at which calls are logged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogPoint {
+    /// Before the original function is called, without the result.
+    Entry,
+    /// After the original function returned, including the result.
+    Exit,
+    /// Both before and after calling the original function.
+    Both,
+}
+
+/// The format calls are logged in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// Readable lines written with `fprintf`.
+    Text,
+    /// Records of raw 64 bit words written with `write`.
+    Binary,
+}
+
+/// The handle the original functions are looked up with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DlsymHandle {
+    /// `RTLD_NEXT`, searching the libraries loaded after the stubs.
+    Next,
+    /// `RTLD_DEFAULT`, searching the global scope.
+    Default,
+}
+
+impl DlsymHandle {
+    /// Returns the constant for this handle.
+    fn constant(self) -> &'static str {
+        match self {
+            DlsymHandle::Next => "RTLD_NEXT",
+            DlsymHandle::Default => "RTLD_DEFAULT",
         }
+    }
+}
+
+/// A part of the template of a log line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplatePart {
+    /// Text that is logged as is.
+    Text(String),
+    /// The name of the function, written as `{fn}`.
+    Function,
+    /// The arguments separated by commas, written as `{args}`.
+    Arguments,
+    /// The result, if it is logged, written as `{ret}`.
+    Result,
+    /// The id of the process, written as `{pid}`.
+    Pid,
+    /// The id of the thread, written as `{tid}`.
+    Tid,
+}
+
+/// A failure that a stub returns instead of calling the original function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    /// The value that is returned.
+    pub value: String,
+    /// The value that `errno` is set to.
+    pub errno: String,
+}
+
+/// The keywords that type specifiers are made of.
+const SPECIFIER_KEYWORDS: &[&str] = &[
+    "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "_Bool",
+    "bool", "_Complex", "size_t", "ssize_t", "off_t", "wchar_t", "int8_t", "int16_t", "int32_t",
+    "int64_t", "intptr_t", "intmax_t", "uint8_t", "uint16_t", "uint32_t", "uint64_t",
+    "uintptr_t", "uintmax_t", "div_t", "ldiv_t", "lldiv_t", "imaxdiv_t",
+];
+
+/// The structure types that are returned by value by standard functions.
+const STRUCT_SPECIFIERS: &[&str] = &["div_t", "ldiv_t", "lldiv_t", "imaxdiv_t"];
 
-        write!(f, "}}\n")
+/// The signed types that are logged as `intmax_t`.
+const SIGNED_WIDE_SPECIFIERS: &[&str] = &[
+    "off_t", "int8_t", "int16_t", "int32_t", "int64_t", "intptr_t", "intmax_t",
+];
+
+/// The unsigned types that are logged as `uintmax_t`.
+const UNSIGNED_WIDE_SPECIFIERS: &[&str] = &[
+    "uint8_t", "uint16_t", "uint32_t", "uint64_t", "uintptr_t", "uintmax_t",
+];
+
+/// The headers that need to be included when a type specifier keyword is used.
+const TYPE_HEADERS: &[(&str, &str)] = &[
+    ("size_t", "stddef.h"),
+    ("ssize_t", "sys/types.h"),
+    ("off_t", "sys/types.h"),
+    ("off_t", "stdint.h"),
+    ("int8_t", "inttypes.h"),
+    ("int16_t", "inttypes.h"),
+    ("int32_t", "inttypes.h"),
+    ("int64_t", "inttypes.h"),
+    ("intptr_t", "inttypes.h"),
+    ("intmax_t", "inttypes.h"),
+    ("uint8_t", "inttypes.h"),
+    ("uint16_t", "inttypes.h"),
+    ("uint32_t", "inttypes.h"),
+    ("uint64_t", "inttypes.h"),
+    ("uintptr_t", "inttypes.h"),
+    ("uintmax_t", "inttypes.h"),
+    ("div_t", "stdlib.h"),
+    ("ldiv_t", "stdlib.h"),
+    ("lldiv_t", "stdlib.h"),
+    ("imaxdiv_t", "inttypes.h"),
+    ("wchar_t", "wchar.h"),
+    ("_Complex", "complex.h"),
+    ("_Bool", "stdbool.h"),
+    ("bool", "stdbool.h"),
+];
+
+/// The type specifiers made of multiple keywords that have their own format.
+const COMBINED_SPECIFIERS: &[&str] = &[
+    "signed int",
+    "unsigned int",
+    "long int",
+    "signed long",
+    "signed long int",
+    "unsigned long",
+    "unsigned long int",
+    "long double",
+    "float _Complex",
+    "double _Complex",
+    "long double _Complex",
+];
+
+/// The format used for values of types without a format of their own.
+const UNKNOWN_TYPE_FORMAT: &str = "{Unknown Type: %d}";
+
+/// The format used for structures, which cannot be passed to `fprintf`.
+const STRUCT_FORMAT: &str = "{struct}";
+
+/// Lists the supported type specifiers with the format their values are logged with.
+pub fn list_supported_types(config: &Config) -> String {
+    let mut output = String::new();
+
+    for specifier in SPECIFIER_KEYWORDS.iter().chain(COMBINED_SPECIFIERS) {
+        let value = Type::new(vec![], specifier.to_string(), vec![]);
+        let format = match value.get_format_specifier(config) {
+            _ if value.is_void() => "not logged",
+            UNKNOWN_TYPE_FORMAT => "fallback",
+            format => format,
+        };
+
+        output.push_str(&format!("{}\t{}\n", specifier, format));
     }
+
+    let pointer = Type::new(vec![], "void".to_string(), vec![vec![]]);
+    output.push_str(&format!(
+        "pointers\t{}\n",
+        pointer.get_format_specifier(config)
+    ));
+
+    output
 }
 
-/// Parses a C function.
+/// A condition under which a stub fails, comparing a parameter to a value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    /// The name of the compared parameter.
+    pub parameter: String,
+    /// The value the parameter is compared to.
+    pub value: String,
+}
+
+/// Functions that are never resolved through the dynamic linker, so stubs for them are never called.
+const NON_INTERPOSABLE_FUNCTIONS: &[&str] = &["main", "_start", "_init", "_fini"];
+
+/// Variadic functions and the variants taking their variable arguments as a `va_list`.
+const VA_LIST_VARIANTS: &[(&str, &str)] = &[
+    ("printf", "vprintf"),
+    ("fprintf", "vfprintf"),
+    ("dprintf", "vdprintf"),
+    ("sprintf", "vsprintf"),
+    ("snprintf", "vsnprintf"),
+    ("asprintf", "vasprintf"),
+    ("scanf", "vscanf"),
+    ("fscanf", "vfscanf"),
+    ("sscanf", "vsscanf"),
+    ("syslog", "vsyslog"),
+    ("err", "verr"),
+    ("errx", "verrx"),
+    ("warn", "vwarn"),
+    ("warnx", "vwarnx"),
+    ("wprintf", "vwprintf"),
+    ("fwprintf", "vfwprintf"),
+    ("swprintf", "vswprintf"),
+    ("wscanf", "vwscanf"),
+    ("fwscanf", "vfwscanf"),
+    ("swscanf", "vswscanf"),
+];
+
+/// Reports functions whose stubs would never be called.
+///
+/// These are warnings, unless strict checking is enabled.
+fn check_interposable(
+    functions: &[Function],
+    config: &Config,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), String> {
+    for function in functions {
+        let message = if NON_INTERPOSABLE_FUNCTIONS.contains(&&function.name[..]) {
+            format!("'{}' cannot be interposed", function.name)
+        } else if function.internal {
+            format!(
+                "'{}' is declared static or inline and cannot be interposed",
+                function.name
+            )
+        } else {
+            continue;
+        };
+
+        if config.strict {
+            Err(message)?;
+        } else {
+            diagnostics.warning(message);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reports functions declared without a prototype, whose stubs assume they take no arguments.
+///
+/// These are warnings, unless strict checking is enabled.
+fn check_prototypes(
+    functions: &[Function],
+    config: &Config,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), String> {
+    for function in functions.iter().filter(|function| function.unprototyped) {
+        let message = format!(
+            "'{}' is declared without a prototype and is assumed to take no arguments",
+            function.name
+        );
+
+        if config.strict {
+            Err(message)?;
+        } else {
+            diagnostics.warning(message);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that every failing function returns a value exactly if it has a return type.
+///
+/// Failure rates and conditions are only valid for functions that have a failure.
+fn check_failures(functions: &[Function], config: &Config) -> Result<(), String> {
+    for name in config.fail_rates.keys() {
+        if !config.failures.contains_key(name) {
+            Err(format!("'{}' has a failure rate, but no failure", name))?;
+        }
+    }
+
+    for name in config.fail_conditions.keys() {
+        if !config.failures.contains_key(name) {
+            Err(format!("'{}' has a condition, but no failure", name))?;
+        }
+    }
+
+    for function in functions {
+        if config.abort_functions.contains(&function.name)
+            && config.failures.contains_key(&function.name)
+        {
+            Err(format!("'{}' cannot both fail and abort", function.name))?;
+        }
+
+        if let Some(condition) = config.fail_conditions.get(&function.name) {
+            if !function
+                .parameters
+                .iter()
+                .any(|parameter| parameter.1 == condition.parameter)
+            {
+                Err(format!(
+                    "'{}' has no parameter '{}'",
+                    function.name, condition.parameter
+                ))?;
+            }
+        }
+
+        if let Some(failure) = config.failures.get(&function.name) {
+            if function.return_type.is_void() && !failure.value.is_empty() {
+                Err(format!("'{}' returns void and cannot fail with a value", function.name))?;
+            } else if !function.return_type.is_void() && failure.value.is_empty() {
+                Err(format!("'{}' needs a value to fail with", function.name))?;
+            } else if !function.return_type.is_void() {
+                if let Some(expected) = function.return_type.check_value(&failure.value) {
+                    Err(format!(
+                        "'{}' returns {} and cannot fail with '{}', expected {}",
+                        function.name, function.return_type, failure.value, expected
+                    ))?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that buffers are pointers and their lengths are parameters of the same function.
+fn check_buffer_lengths(functions: &[Function]) -> Result<(), String> {
+    for function in functions {
+        for parameter in &function.parameters {
+            if let Some(length) = &parameter.3 {
+                if parameter.0.pointers.is_empty() {
+                    Err(format!(
+                        "The buffer '{}' of '{}' is not a pointer",
+                        parameter.1, function.name
+                    ))?;
+                }
+
+                if !function.parameters.iter().any(|other| &other.1 == length) {
+                    Err(format!("'{}' has no parameter '{}'", function.name, length))?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that no function has two parameters with the same name.
+fn check_parameter_names(functions: &[Function]) -> Result<(), String> {
+    for function in functions {
+        for (i, parameter) in function.parameters.iter().enumerate() {
+            if function.parameters[..i].iter().any(|other| other.1 == parameter.1) {
+                Err(format!(
+                    "The parameter '{}' of '{}' is declared more than once",
+                    parameter.1, function.name
+                ))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that variadic functions have a fixed parameter to find the variable arguments with.
+fn check_variadic(functions: &[Function]) -> Result<(), String> {
+    for function in functions {
+        if function.variadic && function.parameters.is_empty() {
+            Err(format!(
+                "'{}' takes only variable arguments, which cannot be forwarded",
+                function.name
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds the functions that the variable arguments of variadic functions are forwarded to.
+///
+/// The variable arguments cannot be forwarded without such a function, so it is an error if none
+/// is known.
+fn find_va_list_variants(functions: &mut [Function], config: &Config) -> Result<(), String> {
+    for function in functions.iter_mut().filter(|function| function.variadic) {
+        let original_name = config.aliases.get(&function.name).unwrap_or(&function.name);
+        function.va_list_variant = config
+            .va_list_variants
+            .get(&function.name)
+            .cloned()
+            .or_else(|| {
+                VA_LIST_VARIANTS
+                    .iter()
+                    .find(|(name, _)| name == original_name)
+                    .map(|(_, variant)| variant.to_string())
+            });
+
+        if function.va_list_variant.is_none() {
+            Err(format!(
+                "'{}' has no known va_list variant to forward its variable arguments to, \
+                 use --va-list-variant to name one",
+                function.name
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Rewrites the types of the functions in a way that C++ compilers accept.
+fn adapt_to_cxx(functions: &mut [Function]) -> Result<(), String> {
+    for function in functions {
+        let name = &function.name;
+        let types = function
+            .parameters
+            .iter_mut()
+            .map(|parameter| &mut parameter.0)
+            .chain(Some(&mut function.return_type));
+
+        for function_type in types {
+            function_type
+                .adapt_to_cxx()
+                .map_err(|err| format!("'{}' cannot be written as C++: {}", name, err))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes repeated declarations of the same function.
+///
+/// Identical redeclarations are skipped with a warning, while conflicting ones are an error.
+fn remove_duplicates(
+    functions: Vec<Function>,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Function>, String> {
+    let mut unique_functions: Vec<Function> = Vec::with_capacity(functions.len());
+    let mut indices: HashMap<String, usize> = HashMap::new();
+
+    for function in functions {
+        if let Some(&index) = indices.get(&function.name) {
+            if unique_functions[index].has_same_signature(&function) {
+                diagnostics.warning(format_args!(
+                    "skipping duplicate declaration of '{}'",
+                    function.name
+                ));
+            } else {
+                Err(format!("Conflicting declarations of '{}'", function.name))?;
+            }
+        } else {
+            indices.insert(function.name.clone(), unique_functions.len());
+            unique_functions.push(function);
+        }
+    }
+
+    Ok(unique_functions)
+}
+
+/// Represents a C type qualifier.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TypeQualifier {
+    /// The const type qualifier.
+    Const,
+    /// The restrict type qualifier.
+    Restrict,
+    /// The `__restrict` extension, which is how `restrict` is written in C++.
+    RestrictExtension,
+    /// The volatile type qualifier.
+    Volatile,
+    /// The _Atomic type qualifier.
+    Atomic,
+}
+
+impl fmt::Display for TypeQualifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeQualifier::Const => write!(f, "const"),
+            TypeQualifier::Restrict => write!(f, "restrict"),
+            TypeQualifier::RestrictExtension => write!(f, "__restrict"),
+            TypeQualifier::Volatile => write!(f, "volatile"),
+            TypeQualifier::Atomic => write!(f, "_Atomic"),
+        }
+    }
+}
+
+/// Represents a type in C.
+///
+/// # Note
+/// This does not yet correspond to the C standard and just supports a subset of possible types.
+#[derive(Debug, PartialEq)]
+struct Type {
+    /// The qualifiers used in this type.
+    qualifiers: Vec<TypeQualifier>,
+    /// The specifier used for this type.
+    specifier: String,
+    /// The qualifiers of each pointer indirection on this type.
+    pointers: Vec<Vec<TypeQualifier>>,
+    /// The contents of the brackets, if this is a parameter declared as an array.
+    ///
+    /// Array parameters are pointers, so the outermost pointer stands for the array.
+    array: Option<String>,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for qualifier in &self.qualifiers {
+            write!(f, "{} ", qualifier)?;
+        }
+
+        write!(f, "{}", self.specifier)?;
+
+        if !self.pointers.is_empty() {
+            write!(f, " ")?;
+        }
+
+        for (i, qualifiers) in self.pointers.iter().enumerate() {
+            write!(f, "*")?;
+
+            for (j, qualifier) in qualifiers.iter().enumerate() {
+                if j != 0 {
+                    write!(f, " ")?;
+                }
+
+                write!(f, "{}", qualifier)?;
+            }
+
+            if !qualifiers.is_empty() && i != self.pointers.len() - 1 {
+                write!(f, " ")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Type {
+    /// Creates a new type.
+    ///
+    /// `restrict` only applies to pointers, so if it is written before the specifier of a pointer
+    /// type, it is moved to the outermost pointer.
+    fn new(
+        mut qualifiers: Vec<TypeQualifier>,
+        specifier: String,
+        mut pointers: Vec<Vec<TypeQualifier>>,
+    ) -> Type {
+        if let Some(outermost) = pointers.last_mut() {
+            if qualifiers.contains(&TypeQualifier::Restrict) {
+                qualifiers.retain(|qualifier| *qualifier != TypeQualifier::Restrict);
+
+                if !outermost.contains(&TypeQualifier::Restrict) {
+                    outermost.push(TypeQualifier::Restrict);
+                }
+            }
+        }
+
+        Type {
+            qualifiers,
+            specifier,
+            pointers,
+            array: None,
+        }
+    }
+
+    /// Turns this type into the type of a parameter declared as an array of it.
+    ///
+    /// The qualifiers in the brackets apply to the pointer the array is adjusted to.
+    fn into_array(mut self, is_static: bool, qualifiers: Vec<TypeQualifier>, size: &str) -> Type {
+        let mut array: Vec<_> = qualifiers.iter().map(|qualifier| qualifier.to_string()).collect();
+        if is_static {
+            array.insert(0, "static".to_string());
+        }
+        if !size.is_empty() {
+            array.push(size.to_string());
+        }
+
+        self.pointers.push(qualifiers);
+        self.array = Some(array.join(" "));
+        self
+    }
+
+    /// Rewrites this type in a way that C++ compilers accept.
+    ///
+    /// `restrict` is replaced by the `__restrict` extension and arrays are written as the pointers
+    /// they are adjusted to. `_Atomic` has no equivalent, so it is an error.
+    fn adapt_to_cxx(&mut self) -> Result<(), String> {
+        let qualifiers = self
+            .pointers
+            .iter_mut()
+            .chain(Some(&mut self.qualifiers))
+            .flat_map(|qualifiers| qualifiers.iter_mut());
+
+        for qualifier in qualifiers {
+            match qualifier {
+                TypeQualifier::Restrict => *qualifier = TypeQualifier::RestrictExtension,
+                TypeQualifier::Atomic => Err("_Atomic is not supported in C++")?,
+                _ => (),
+            }
+        }
+
+        self.array = None;
+
+        Ok(())
+    }
+
+    /// Writes a declaration of the given declarator with this type.
+    ///
+    /// The declarator directly follows a `*`, but is separated from keywords by a space.
+    fn write_declaration(&self, f: &mut dyn Write, declarator: &str) -> fmt::Result {
+        if let Some(array) = &self.array {
+            let element = Type {
+                qualifiers: self.qualifiers.clone(),
+                specifier: self.specifier.clone(),
+                pointers: self.pointers[..self.pointers.len() - 1].to_vec(),
+                array: None,
+            };
+
+            return element.write_declaration(f, &format!("{}[{}]", declarator, array));
+        }
+
+        let type_name = self.to_string();
+
+        if type_name.ends_with('*') {
+            write!(f, "{}{}", type_name, declarator)
+        } else {
+            write!(f, "{} {}", type_name, declarator)
+        }
+    }
+
+    /// Returns a format specifier for this type.
+    fn get_format_specifier(&self, config: &Config) -> &'static str {
+        self.get_radix_format_specifier(config, config.int_radix)
+    }
+
+    /// Returns a format specifier for this type, taking the annotation of the value into account.
+    fn get_annotated_format_specifier(
+        &self,
+        config: &Config,
+        annotation: Option<FormatAnnotation>,
+    ) -> &'static str {
+        match annotation {
+            Some(FormatAnnotation::Decimal) => {
+                self.get_radix_format_specifier(config, IntRadix::Decimal)
+            }
+            Some(FormatAnnotation::Hex) => self.get_radix_format_specifier(config, IntRadix::Hex),
+            Some(FormatAnnotation::String) => "\\\"%s\\\"",
+            Some(FormatAnnotation::Pointer) => pointer_format_specifier(config),
+            None => self.get_format_specifier(config),
+        }
+    }
+
+    /// Returns a format specifier for this type, logging integers in the given radix.
+    fn get_radix_format_specifier(&self, config: &Config, radix: IntRadix) -> &'static str {
+        match (&self.specifier[..], self.pointers.len()) {
+            ("char", 1) => "\\\"%s\\\"",
+            ("char", 2) if config.deref_strings => "\\\"%s\\\"",
+            ("wchar_t", 1) => "\\\"%ls\\\"",
+            ("wchar_t", 0) => "%lc",
+            ("int", 0) | ("signed", 0) | ("signed int", 0) => match radix {
+                IntRadix::Decimal => "%d",
+                IntRadix::Hex => "0x%x",
+            },
+            ("unsigned", 0) | ("unsigned int", 0) => match radix {
+                IntRadix::Decimal => "%u",
+                IntRadix::Hex => "0x%x",
+            },
+            ("long", 0) | ("long int", 0) | ("signed long", 0) | ("signed long int", 0) => {
+                match radix {
+                    IntRadix::Decimal => "%ld",
+                    IntRadix::Hex => "0x%lx",
+                }
+            }
+            ("unsigned long", 0) | ("unsigned long int", 0) => match radix {
+                IntRadix::Decimal => "%lu",
+                IntRadix::Hex => "0x%lx",
+            },
+            ("size_t", 0) => match radix {
+                IntRadix::Decimal => "%zu",
+                IntRadix::Hex => "0x%zx",
+            },
+            ("ssize_t", 0) => match radix {
+                IntRadix::Decimal => "%zd",
+                IntRadix::Hex => "0x%zx",
+            },
+            (specifier, 0) if SIGNED_WIDE_SPECIFIERS.contains(&specifier) => match radix {
+                IntRadix::Decimal => "%jd",
+                IntRadix::Hex => "0x%jx",
+            },
+            (specifier, 0) if UNSIGNED_WIDE_SPECIFIERS.contains(&specifier) => match radix {
+                IntRadix::Decimal => "%ju",
+                IntRadix::Hex => "0x%jx",
+            },
+            // `float` is promoted to `double` when passed to `fprintf`.
+            ("float", 0) | ("double", 0) => "%f",
+            ("long double", 0) => "%Lf",
+            ("float _Complex", 0) | ("double _Complex", 0) => "%f+%fi",
+            ("long double _Complex", 0) => "%Lf+%Lfi",
+            // `bool` is the name of `_Bool` in `stdbool.h`.
+            ("_Bool", 0) | ("bool", 0) if config.bool_names => "%s",
+            // `_Bool` is promoted to `int` when passed to `fprintf`.
+            ("_Bool", 0) | ("bool", 0) => "%d",
+            (specifier, 0) if STRUCT_SPECIFIERS.contains(&specifier) => STRUCT_FORMAT,
+            (_, 0) => UNKNOWN_TYPE_FORMAT,
+            (_, _) => pointer_format_specifier(config),
+        }
+    }
+
+    /// Writes the argument matching the format specifier for the given value of this type.
+    fn write_format_argument(&self, f: &mut dyn Write, value: &str, config: &Config) -> fmt::Result {
+        match (&self.specifier[..], self.pointers.len()) {
+            ("_Bool", 0) | ("bool", 0) if config.bool_names => {
+                write!(f, "({} ? \"true\" : \"false\")", value)
+            }
+            // Passing NULL for a string is undefined behavior, so it is replaced.
+            ("char", 1) => write!(f, "({0} ? {0} : \"(null)\")", value),
+            ("char", 2) if config.deref_strings => {
+                write!(f, "({0} && *{0} ? *{0} : \"(null)\")", value)
+            }
+            ("wchar_t", 1) => write!(f, "({0} ? {0} : L\"(null)\")", value),
+            ("wchar_t", 0) => write!(f, "(wint_t){}", value),
+            // Types without a length modifier of their own are converted to the widest one.
+            (specifier, 0) if SIGNED_WIDE_SPECIFIERS.contains(&specifier) => {
+                write!(f, "(intmax_t){}", value)
+            }
+            (specifier, 0) if UNSIGNED_WIDE_SPECIFIERS.contains(&specifier) => {
+                write!(f, "(uintmax_t){}", value)
+            }
+            // Complex numbers cannot be passed to `fprintf`, so they are split into their parts.
+            ("float _Complex", 0) => write!(f, "crealf({0}), cimagf({0})", value),
+            ("double _Complex", 0) => write!(f, "creal({0}), cimag({0})", value),
+            ("long double _Complex", 0) => write!(f, "creall({0}), cimagl({0})", value),
+            (_, 0) => write!(f, "{}", value),
+            (_, _) => match config.pointer_format {
+                PointerFormat::Native => write!(f, "{}", value),
+                PointerFormat::Hex => write!(f, "(unsigned long)(uintptr_t){}", value),
+            },
+        }
+    }
+
+    /// Writes the argument matching the annotated format specifier for the given value of this type.
+    fn write_annotated_format_argument(
+        &self,
+        f: &mut dyn Write,
+        value: &str,
+        config: &Config,
+        annotation: Option<FormatAnnotation>,
+    ) -> fmt::Result {
+        match annotation {
+            Some(FormatAnnotation::String) => {
+                write!(f, "((const char *){0} ? (const char *){0} : \"(null)\")", value)
+            }
+            Some(FormatAnnotation::Pointer) => match config.pointer_format {
+                PointerFormat::Native => write!(f, "(void *){}", value),
+                PointerFormat::Hex => write!(f, "(unsigned long)(uintptr_t){}", value),
+            },
+            _ => self.write_format_argument(f, value, config),
+        }
+    }
+
+    /// Returns true, if the given keyword is part of the specifier of this type.
+    fn uses_specifier(&self, keyword: &str) -> bool {
+        self.specifier.split(' ').any(|word| word == keyword)
+    }
+
+    /// Returns this type without the qualifiers that apply to the value itself.
+    ///
+    /// For pointers these are the qualifiers of the outermost pointer.
+    fn without_top_level_qualifiers(&self) -> Type {
+        let mut qualifiers = self.qualifiers.clone();
+        let mut pointers = self.pointers.clone();
+
+        match pointers.last_mut() {
+            Some(pointer_qualifiers) => pointer_qualifiers.clear(),
+            None => qualifiers.clear(),
+        }
+
+        Type {
+            qualifiers,
+            specifier: self.specifier.clone(),
+            pointers,
+            array: None,
+        }
+    }
+
+    /// Checks that the given C expression is a constant of this type.
+    ///
+    /// Returns a description of the expected values if it is not. Values of types that cannot be
+    /// checked, like structures, are accepted.
+    fn check_value(&self, value: &str) -> Option<&'static str> {
+        let value = value.trim();
+
+        if !self.pointers.is_empty() {
+            let is_string = value.len() >= 2 && value.starts_with('"') && value.ends_with('"');
+
+            return if value == "NULL" || value == "0" || is_string {
+                None
+            } else {
+                Some("NULL, 0 or a string literal")
+            };
+        }
+
+        match &self.specifier[..] {
+            "float" | "double" | "long double" => {
+                let number = value.trim_start_matches(['+', '-']);
+                let number = number.trim_end_matches(['f', 'F', 'l', 'L']);
+
+                if number.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+                    && number.parse::<f64>().is_ok()
+                {
+                    None
+                } else {
+                    Some("a floating point number")
+                }
+            }
+            "_Bool" | "bool" if value == "true" || value == "false" => None,
+            specifier
+                if specifier.contains("_Complex") || STRUCT_SPECIFIERS.contains(&specifier) =>
+            {
+                None
+            }
+            _ if is_integer_constant(value) => None,
+            _ => Some("an integer or a character constant"),
+        }
+    }
+
+    /// Returns true, if this type is the void type.
+    ///
+    /// Qualified void types like `const void` are void as well, but pointers to them are not.
+    fn is_void(&self) -> bool {
+        matches!((&self.specifier[..], self.pointers.len()), ("void", 0))
+    }
+}
+
+/// Returns true, if the given text is a C integer or character constant, optionally negated.
+fn is_integer_constant(value: &str) -> bool {
+    let value = value.trim_start_matches(['+', '-']);
+
+    if value.len() >= 3 && value.starts_with('\'') && value.ends_with('\'') {
+        return true;
+    }
+
+    let value = value.trim_end_matches(['u', 'U', 'l', 'L']);
+    let (digits, radix) = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(digits) => (digits, 16),
+        None => (value, 10),
+    };
+
+    !digits.is_empty() && digits.chars().all(|c| c.is_digit(radix))
+}
+
+/// Returns the format specifier for pointers.
+fn pointer_format_specifier(config: &Config) -> &'static str {
+    match config.pointer_format {
+        PointerFormat::Native => "%p",
+        PointerFormat::Hex => "0x%016lx",
+    }
+}
+
+/// An annotation of a parameter that changes the format it is logged with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FormatAnnotation {
+    /// Integers are logged as decimal numbers, written as `/*dec*/`.
+    Decimal,
+    /// Integers are logged as hexadecimal numbers, written as `/*hex*/`.
+    Hex,
+    /// The value is logged as a string, written as `/*string*/`.
+    String,
+    /// The value is logged as a pointer, written as `/*pointer*/`.
+    Pointer,
+}
+
+/// Represents a C function.
+#[derive(Debug, PartialEq)]
+struct Function {
+    /// The return type of the function.
+    return_type: Type,
+    /// The name identifying the function.
+    name: String,
+    /// The parameters of the function with the annotation of their format and the parameter
+    /// holding their length, if they are buffers.
+    parameters: Vec<(Type, String, Option<FormatAnnotation>, Option<String>)>,
+    /// Whether the function takes a variable number of arguments after its parameters.
+    variadic: bool,
+    /// Whether the function is declared without a prototype, like `int foo();`.
+    unprototyped: bool,
+    /// The library the original function is loaded from, if it is not searched for normally.
+    library: Option<String>,
+    /// The number identifying the function in binary logs.
+    id: usize,
+    /// The text of the prototype the function was parsed from.
+    source: String,
+    /// Whether the function is declared `static` or `inline`.
+    internal: bool,
+    /// The function taking the variable arguments as a `va_list` that calls are forwarded to.
+    va_list_variant: Option<String>,
+}
+
+impl Function {
+    /// Returns true, if the given specifier keyword is used in the return or parameter types.
+    fn uses_specifier(&self, keyword: &str) -> bool {
+        self.return_type.uses_specifier(keyword)
+            || self
+                .parameters
+                .iter()
+                .any(|parameter| parameter.0.uses_specifier(keyword))
+    }
+
+    /// Returns true, if both functions have the same return and parameter types.
+    fn has_same_signature(&self, other: &Function) -> bool {
+        self.return_type == other.return_type
+            && self.variadic == other.variadic
+            && self.parameters.len() == other.parameters.len()
+            && self
+                .parameters
+                .iter()
+                .zip(&other.parameters)
+                .all(|(parameter, other_parameter)| parameter.0 == other_parameter.0)
+    }
+
+    /// Writes the signature of this function. Optionally as a function pointer with the given name.
+    ///
+    /// Qualifiers of the returned value itself have no meaning and are left out of function
+    /// pointers, which avoids warnings about ignored qualifiers in the stub.
+    ///
+    /// The variable arguments of variadic functions are passed to function pointers as a
+    /// `va_list`, if they are forwarded to a variant taking them that way. Functions without
+    /// parameters are declared with `(void)`, unless they were declared without a prototype.
+    fn get_signature(&self, f: &mut dyn Write, pointer_name: Option<&str>) -> fmt::Result {
+        if let Some(pointer_name) = pointer_name {
+            self.return_type
+                .without_top_level_qualifiers()
+                .write_declaration(f, &format!("(*{})", pointer_name))?;
+        } else {
+            self.return_type.write_declaration(f, &self.name)?;
+        }
+
+        let mut parameters = Vec::new();
+        for parameter in &self.parameters {
+            let mut declaration = String::new();
+            parameter.0.write_declaration(&mut declaration, &parameter.1)?;
+            parameters.push(declaration);
+        }
+
+        if self.va_list_variant.is_some() && pointer_name.is_some() {
+            parameters.push("va_list ldpsc_arguments".to_string());
+        } else if self.variadic {
+            parameters.push("...".to_string());
+        } else if parameters.is_empty() && !self.unprototyped {
+            parameters.push("void".to_string());
+        }
+
+        write!(f, "({})", parameters.join(", "))
+    }
+
+    /// Returns the normalized prototype of this function.
+    ///
+    /// Parsing the prototype results in the same function, except for annotations in comments.
+    fn to_prototype_string(&self) -> String {
+        let mut prototype = String::new();
+        self.get_signature(&mut prototype, None)
+            .expect("writing to a string cannot fail");
+        prototype.push(';');
+        prototype
+    }
+
+    /// Writes the definition of this function.
+    fn get_definition(&self, f: &mut dyn Write, config: &Config) -> fmt::Result {
+        if config.annotate {
+            // The canonical form of the prototype is the one the stub is declared with.
+            let source = if config.canonical {
+                self.to_prototype_string()
+            } else {
+                self.source.clone()
+            };
+
+            // The prototype may contain comments itself, which must not end this one.
+            writeln!(f, "/* from input: {} */", source.replace("*/", "* /"))?;
+        }
+
+        if config.export_symbols {
+            writeln!(f, "__attribute__((visibility(\"default\")))")?;
+        }
+
+        self.get_signature(f, None)?;
+        writeln!(f, " {{")?;
+
+        write_open_output(f, config, self.logs_raw(config))?;
+
+        if let Some(max_calls) = config.max_calls {
+            writeln!(f, "    static unsigned long ldpsc_calls;")?;
+            writeln!(
+                f,
+                "    int ldpsc_logged = \
+                 __atomic_add_fetch(&ldpsc_calls, 1, __ATOMIC_RELAXED) <= {};",
+                max_calls
+            )?;
+        }
+
+        if logs_sequence(config) {
+            writeln!(
+                f,
+                "    unsigned long ldpsc_sequence_number = \
+                 __atomic_add_fetch(&ldpsc_sequence, 1, __ATOMIC_RELAXED);"
+            )?;
+        }
+
+        // The condition is checked first, so random numbers are only used when it holds.
+        let mut conditions = Vec::new();
+        if let Some(condition) = config.fail_conditions.get(&self.name) {
+            conditions.push(self.condition_expression(condition));
+        }
+        if let Some(rate) = config.fail_rates.get(&self.name) {
+            conditions.push(format!("rand() % 100 < {}", rate));
+        }
+
+        if config.abort_functions.contains(&self.name) {
+            self.write_aborting_body(f, config)?;
+            return writeln!(f, "}}");
+        }
+
+        match config.failures.get(&self.name) {
+            Some(failure) if !conditions.is_empty() => {
+                let mut failing_body = String::new();
+                self.write_failing_body(&mut failing_body, config, failure)?;
+
+                writeln!(f, "    if ({}) {{", conditions.join(" && "))?;
+                for line in failing_body.lines() {
+                    writeln!(f, "    {}", line)?;
+                }
+                if self.return_type.is_void() {
+                    writeln!(f, "        return;")?;
+                }
+                writeln!(f, "    }}")?;
+
+                self.write_forwarding_body(f, config)?;
+            }
+            Some(failure) => self.write_failing_body(f, config, failure)?,
+            None => self.write_forwarding_body(f, config)?,
+        }
+
+        writeln!(f, "}}")
+    }
+
+    /// Returns the C expression checking the given condition.
+    ///
+    /// Strings are compared by their content.
+    fn condition_expression(&self, condition: &Condition) -> String {
+        let is_string = self
+            .parameters
+            .iter()
+            .find(|parameter| parameter.1 == condition.parameter)
+            .map(|parameter| parameter.0.specifier == "char" && parameter.0.pointers.len() == 1)
+            .unwrap_or(false);
+
+        if is_string {
+            format!(
+                "{0} && strcmp({0}, \"{1}\") == 0",
+                condition.parameter,
+                escape_string_literal(&condition.value)
+            )
+        } else {
+            format!("{} == {}", condition.parameter, condition.value)
+        }
+    }
+
+    /// Writes the body of a stub that logs the call and forwards it to the original function.
+    ///
+    /// Variadic functions are forwarded to the `v` variant of the original function, which takes
+    /// the variable arguments as a `va_list`. Only the fixed parameters are logged.
+    fn write_forwarding_body(&self, f: &mut dyn Write, config: &Config) -> fmt::Result {
+        let keep_result = !self.return_type.is_void();
+        let original_name = config.aliases.get(&self.name).unwrap_or(&self.name);
+        let original_name = self.va_list_variant.as_ref().unwrap_or(original_name);
+        let pointer_name = format!("{}{}", config.orig_prefix, self.name);
+
+        // The original function is only searched for once. Threads racing to do so store the same
+        // pointer, so atomic accesses are enough to make this thread safe.
+        write!(f, "    static ")?;
+        self.get_signature(f, Some(&pointer_name))?;
+        writeln!(f, ";")?;
+        writeln!(
+            f,
+            "    if (!__atomic_load_n(&{}, __ATOMIC_ACQUIRE)) {{",
+            pointer_name
+        )?;
+
+        // If the library cannot be loaded, the function is searched for normally.
+        let handle = match &self.library {
+            Some(library) => {
+                writeln!(
+                    f,
+                    "        void *ldpsc_library_handle = dlopen(\"{}\", RTLD_LAZY);",
+                    escape_string_literal(library)
+                )?;
+                format!(
+                    "ldpsc_library_handle ? ldpsc_library_handle : {}",
+                    config.dlsym_handle.constant()
+                )
+            }
+            None => config.dlsym_handle.constant().to_string(),
+        };
+
+        let lookup = format!("dlsym({}, \"{}\")", handle, original_name);
+        let lookup = if config.dlsym_handle == DlsymHandle::Default {
+            // The global scope contains the stub itself if it is preloaded, which would call itself
+            // forever, so the next definition is used instead.
+            writeln!(f, "        void *ldpsc_original = {};", lookup)?;
+            writeln!(f, "        if (ldpsc_original == (void *){}) {{", self.name)?;
+            writeln!(
+                f,
+                "            ldpsc_original = dlsym(RTLD_NEXT, \"{}\");",
+                original_name
+            )?;
+            writeln!(f, "        }}")?;
+            "ldpsc_original".to_string()
+        } else {
+            lookup
+        };
+
+        write!(f, "        __atomic_store_n(&{}, (", pointer_name)?;
+        self.get_signature(f, Some(""))?;
+        writeln!(f, "){}, __ATOMIC_RELEASE);", lookup)?;
+        writeln!(f, "    }}")?;
+        self.write_missing_original_check(f, config, &pointer_name, original_name)?;
+
+        if config.log_point != LogPoint::Exit {
+            self.write_log(f, config, None)?;
+            self.write_backtrace(f, config)?;
+
+            // The original function may crash, so the log needs to be written before calling it.
+            if self.flushes_output(config) && !self.flushes_log(config) {
+                writeln!(f, "    fflush(output);")?;
+            }
+        }
+
+        if let Some(last) = self.parameters.last().filter(|_| self.va_list_variant.is_some()) {
+            writeln!(f, "    va_list ldpsc_arguments;")?;
+            writeln!(f, "    va_start(ldpsc_arguments, {});", last.1)?;
+        }
+
+        write!(f, "    ")?;
+
+        if keep_result {
+            self.return_type.write_declaration(f, "result")?;
+            write!(f, " = ")?;
+        }
+
+        let mut arguments: Vec<_> = self
+            .parameters
+            .iter()
+            .map(|parameter| &parameter.1[..])
+            .collect();
+        if self.va_list_variant.is_some() {
+            arguments.push("ldpsc_arguments");
+        }
+
+        writeln!(f, "{}({});", pointer_name, arguments.join(", "))?;
+
+        if self.va_list_variant.is_some() {
+            writeln!(f, "    va_end(ldpsc_arguments);")?;
+        }
+
+        if config.hook_header.is_some() {
+            self.write_hook_call(f, keep_result)?;
+        }
+
+        if let Some(record) = &config.record {
+            self.write_record(f, record, keep_result, self.logs_raw(config))?;
+        }
+
+        if config.log_point != LogPoint::Entry {
+            let logged_result = if self.logs_result(config) {
+                Some("result")
+            } else {
+                None
+            };
+            self.write_log(f, config, logged_result)?;
+        }
+
+        if config.log_point == LogPoint::Exit {
+            self.write_backtrace(f, config)?;
+        }
+
+        write_close_output(f, config, self.logs_raw(config))?;
+
+        if keep_result {
+            writeln!(f, "    return result;")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the weak declaration of the hook called after the original function.
+    ///
+    /// The hook takes the arguments and a pointer to the result, if there is one.
+    fn write_hook_declaration(&self, f: &mut dyn Write) -> fmt::Result {
+        write!(f, "__attribute__((weak)) void ldpsc_hook_{}(", self.name)?;
+
+        let mut parameters = String::new();
+        for parameter in &self.parameters {
+            parameter.0.write_declaration(&mut parameters, &parameter.1)?;
+            parameters.push_str(", ");
+        }
+        if !self.return_type.is_void() {
+            self.return_type.write_declaration(&mut parameters, "*result")?;
+        }
+
+        match parameters.trim_end_matches(", ") {
+            "" => writeln!(f, "void);"),
+            parameters => writeln!(f, "{});", parameters),
+        }
+    }
+
+    /// Writes the call of the hook of this function, if it is defined.
+    fn write_hook_call(&self, f: &mut dyn Write, has_result: bool) -> fmt::Result {
+        let mut arguments: Vec<_> = self
+            .parameters
+            .iter()
+            .map(|parameter| &parameter.1[..])
+            .collect();
+        if has_result {
+            arguments.push("&result");
+        }
+
+        writeln!(f, "    if (ldpsc_hook_{}) {{", self.name)?;
+        writeln!(f, "        ldpsc_hook_{}({});", self.name, arguments.join(", "))?;
+        writeln!(f, "    }}")
+    }
+
+    /// Writes the statements appending a record of a call of this function to the given file.
+    ///
+    /// The record is written with a single `writev`, so records of concurrent calls are not mixed.
+    /// Raw records use system calls, since the stub may be one of the functions writing them.
+    fn write_record(
+        &self,
+        f: &mut dyn Write,
+        file: &str,
+        has_result: bool,
+        raw: bool,
+    ) -> fmt::Result {
+        // Each field is a pointer to its data and the expression of its size.
+        let mut fields = Vec::new();
+
+        for parameter in &self.parameters {
+            fields.push((
+                format!("(void *)&{}", parameter.1),
+                format!("sizeof({})", parameter.1),
+            ));
+        }
+
+        for parameter in &self.parameters {
+            if let Some(length) = &parameter.3 {
+                fields.push((
+                    format!("(void *){}", parameter.1),
+                    format!("({} ? (uint64_t)({}) : 0)", parameter.1, length),
+                ));
+            }
+        }
+
+        if has_result {
+            fields.push(("(void *)&result".to_string(), "sizeof(result)".to_string()));
+        }
+
+        writeln!(f, "    {{")?;
+        if raw {
+            writeln!(
+                f,
+                "        int ldpsc_record = (int)syscall(SYS_openat, AT_FDCWD, \"{}\", \
+                 O_WRONLY | O_CREAT | O_APPEND, 0644);",
+                escape_string_literal(file)
+            )?;
+        } else {
+            writeln!(
+                f,
+                "        int ldpsc_record = open(\"{}\", O_WRONLY | O_CREAT | O_APPEND, 0644);",
+                escape_string_literal(file)
+            )?;
+        }
+        writeln!(
+            f,
+            "        uint64_t ldpsc_header[2] = {{{}, {}}};",
+            self.id,
+            fields.len()
+        )?;
+        // Empty initializers are only valid since C23, so the sizes are left out without fields.
+        if !fields.is_empty() {
+            let sizes: Vec<_> = fields.iter().map(|field| &field.1[..]).collect();
+            writeln!(
+                f,
+                "        uint64_t ldpsc_sizes[{}] = {{{}}};",
+                sizes.len(),
+                sizes.join(", ")
+            )?;
+        }
+        writeln!(f, "        struct iovec ldpsc_fields[{}] = {{", 1 + 2 * fields.len())?;
+        writeln!(f, "            {{ldpsc_header, sizeof(ldpsc_header)}},")?;
+
+        for (i, (data, _)) in fields.iter().enumerate() {
+            writeln!(
+                f,
+                "            {{&ldpsc_sizes[{0}], sizeof(ldpsc_sizes[{0}])}}, \
+                 {{{1}, ldpsc_sizes[{0}]}},",
+                i, data
+            )?;
+        }
+
+        writeln!(f, "        }};")?;
+        if raw {
+            writeln!(
+                f,
+                "        (void)syscall(SYS_writev, ldpsc_record, ldpsc_fields, {});",
+                1 + 2 * fields.len()
+            )?;
+            writeln!(f, "        syscall(SYS_close, ldpsc_record);")?;
+        } else {
+            writeln!(
+                f,
+                "        (void)writev(ldpsc_record, ldpsc_fields, {});",
+                1 + 2 * fields.len()
+            )?;
+            writeln!(f, "        close(ldpsc_record);")?;
+        }
+        writeln!(f, "    }}")
+    }
+
+    /// Writes the check that the original function was found, reporting the error of `dlsym`.
+    ///
+    /// Without the original function, void functions return and all others abort, unless they
+    /// return zero with `--null-on-missing`.
+    fn write_missing_original_check(
+        &self,
+        f: &mut dyn Write,
+        config: &Config,
+        pointer_name: &str,
+        original_name: &str,
+    ) -> fmt::Result {
+        // Binary logs cannot contain text, so the error is written to stderr instead.
+        let print_error = match (config.log_format, self.logs_raw(config)) {
+            (LogFormat::Text, false) => "fprintf(output",
+            (LogFormat::Binary, false) => "dprintf(STDERR_FILENO",
+            (LogFormat::Text, true) => "ldpsc_raw_log(output",
+            (LogFormat::Binary, true) => "ldpsc_raw_log(STDERR_FILENO",
+        };
+
+        writeln!(f, "    if (!{}) {{", pointer_name)?;
+        writeln!(
+            f,
+            "        {}, \"ldpsc: could not find '{}': %s\\n\", dlerror());",
+            print_error, original_name
+        )?;
+
+        if self.return_type.is_void() || config.null_on_missing {
+            let mut close_output = String::new();
+            write_close_output(&mut close_output, config, self.logs_raw(config))?;
+
+            for line in close_output.lines() {
+                writeln!(f, "    {}", line)?;
+            }
+
+            if self.return_type.is_void() {
+                writeln!(f, "        return;")?;
+            } else {
+                // A compound literal is zero for scalars, pointers and structures alike.
+                writeln!(f, "        return ({}){{0}};", self.return_type)?;
+            }
+        } else {
+            if self.flushes_output(config) {
+                writeln!(f, "        fflush(output);")?;
+            }
+
+            writeln!(f, "        abort();")?;
+        }
+
+        writeln!(f, "    }}")
+    }
+
+    /// Writes the body of a stub that logs the call and aborts the program.
+    fn write_aborting_body(&self, f: &mut dyn Write, config: &Config) -> fmt::Result {
+        self.write_log(f, config, None)?;
+        self.write_backtrace(f, config)?;
+        write_close_output(f, config, self.logs_raw(config))?;
+        writeln!(f, "    abort();")
+    }
+
+    /// Writes the body of a stub that logs the call and fails without calling the original function.
+    fn write_failing_body(
+        &self,
+        f: &mut dyn Write,
+        config: &Config,
+        failure: &Failure,
+    ) -> fmt::Result {
+        let result = format!("({})({})", self.return_type, failure.value);
+        let logged_result = if config.log_point == LogPoint::Entry || !self.logs_result(config) {
+            None
+        } else {
+            Some(&result[..])
+        };
+
+        self.write_log(f, config, logged_result)?;
+        self.write_backtrace(f, config)?;
+        write_close_output(f, config, self.logs_raw(config))?;
+
+        // Logging may change errno, so it is set last.
+        writeln!(f, "    errno = {};", failure.errno)?;
+
+        if !self.return_type.is_void() {
+            writeln!(f, "    return {};", failure.value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the statement logging a call of this function, optionally including the result.
+    ///
+    /// With a NULL marker, a pointer result that is NULL is logged as the marker.
+    fn write_log(&self, f: &mut dyn Write, config: &Config, result: Option<&str>) -> fmt::Result {
+        if config.max_calls.is_some() {
+            let mut log = String::new();
+            self.write_unlimited_log(&mut log, config, result)?;
+            if self.flushes_log(config) {
+                writeln!(log, "    fflush(output);")?;
+            }
+
+            writeln!(f, "    if (ldpsc_logged) {{")?;
+            for line in log.lines() {
+                writeln!(f, "    {}", line)?;
+            }
+            return writeln!(f, "    }}");
+        }
+
+        self.write_unlimited_log(f, config, result)?;
+        if self.flushes_log(config) {
+            writeln!(f, "    fflush(output);")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the statements logging the backtrace of a call, if backtraces are logged.
+    ///
+    /// Backtraces are written to the file descriptor of the output, so text outputs are flushed
+    /// first to keep the order.
+    fn write_backtrace(&self, f: &mut dyn Write, config: &Config) -> fmt::Result {
+        let depth = match config.backtrace {
+            Some(depth) => depth,
+            None => return Ok(()),
+        };
+
+        let mut backtrace = String::new();
+        let fd = if self.logs_raw(config) {
+            "output"
+        } else if config.log_format == LogFormat::Binary {
+            "STDERR_FILENO"
+        } else {
+            writeln!(backtrace, "    fflush(output);")?;
+            "fileno(output)"
+        };
+        writeln!(backtrace, "    {{")?;
+        writeln!(backtrace, "        void *frames[{}];", depth)?;
+        writeln!(
+            backtrace,
+            "        backtrace_symbols_fd(frames, backtrace(frames, {}), {});",
+            depth, fd
+        )?;
+        writeln!(backtrace, "    }}")?;
+
+        if config.max_calls.is_some() {
+            writeln!(f, "    if (ldpsc_logged) {{")?;
+            for line in backtrace.lines() {
+                writeln!(f, "    {}", line)?;
+            }
+            writeln!(f, "    }}")
+        } else {
+            f.write_str(&backtrace)
+        }
+    }
+
+    /// Writes the statement logging a call of this function, ignoring the limit of logged calls.
+    fn write_unlimited_log(
+        &self,
+        f: &mut dyn Write,
+        config: &Config,
+        result: Option<&str>,
+    ) -> fmt::Result {
+        if config.log_format == LogFormat::Binary {
+            return self.write_binary_log(f, config, result);
+        }
+
+        let result_format = self.return_type.get_format_specifier(config);
+
+        match (result, &config.null_marker) {
+            (Some(result), Some(marker)) if !self.return_type.pointers.is_empty() => {
+                let mut found = String::new();
+                self.write_log_statement(&mut found, config, Some((result_format, Some(result))))?;
+                let mut null = String::new();
+                self.write_log_statement(
+                    &mut null,
+                    config,
+                    Some((&escape_format_string(marker), None)),
+                )?;
+
+                writeln!(f, "    if ({}) {{", result)?;
+                for line in found.lines() {
+                    writeln!(f, "    {}", line)?;
+                }
+                writeln!(f, "    }} else {{")?;
+                for line in null.lines() {
+                    writeln!(f, "    {}", line)?;
+                }
+                writeln!(f, "    }}")
+            }
+            (result, _) => self.write_log_statement(
+                f,
+                config,
+                result.map(|result| (result_format, Some(result))),
+            ),
+        }
+    }
+
+    /// Writes the statements logging a call of this function as a binary record.
+    ///
+    /// The record consists of the id, the number of arguments, the number of following words,
+    /// one word for each argument and one word for the result, if it is logged.
+    fn write_binary_log(
+        &self,
+        f: &mut dyn Write,
+        config: &Config,
+        result: Option<&str>,
+    ) -> fmt::Result {
+        let words = self.parameters.len() + result.map_or(0, |_| 1);
+
+        writeln!(f, "    {{")?;
+        writeln!(
+            f,
+            "        uint64_t ldpsc_log_record[{}] = {{{}, {}, {}}};",
+            3 + words,
+            self.id,
+            self.parameters.len(),
+            words
+        )?;
+
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            writeln!(
+                f,
+                "        ldpsc_store_word(&ldpsc_log_record[{}], &{1}, sizeof({1}));",
+                3 + i,
+                parameter.1
+            )?;
+        }
+
+        if let Some(result) = result {
+            write!(f, "        ")?;
+            self.return_type.write_declaration(f, "ldpsc_logged_result")?;
+            writeln!(f, " = {};", result)?;
+            writeln!(
+                f,
+                "        ldpsc_store_word(&ldpsc_log_record[{}], &ldpsc_logged_result, \
+                 sizeof(ldpsc_logged_result));",
+                3 + self.parameters.len()
+            )?;
+        }
+
+        if self.logs_raw(config) {
+            writeln!(
+                f,
+                "        (void)syscall(SYS_write, output, ldpsc_log_record, \
+                 sizeof(ldpsc_log_record));"
+            )?;
+        } else {
+            writeln!(
+                f,
+                "        (void)write(output, ldpsc_log_record, sizeof(ldpsc_log_record));"
+            )?;
+        }
+        writeln!(f, "    }}")
+    }
+
+    /// Writes the `fprintf` call logging a call of this function.
+    ///
+    /// The result is given as its format and the matching argument, if the format needs one.
+    fn write_log_statement(
+        &self,
+        f: &mut dyn Write,
+        config: &Config,
+        result: Option<(&str, Option<&str>)>,
+    ) -> fmt::Result {
+        let mut format = String::new();
+        let mut arguments = String::new();
+
+        if logs_sequence(config) {
+            format.push_str("#%lu ");
+            arguments.push_str(", ldpsc_sequence_number");
+        }
+        format.push_str(&escape_format_string(&config.log_prefix));
+
+        for part in self.log_template(config, result.is_some()) {
+            match part {
+                TemplatePart::Text(text) => format.push_str(&escape_format_string(&text)),
+                TemplatePart::Function => format.push_str(&self.name),
+                TemplatePart::Arguments => {
+                    for (i, parameter) in self.parameters.iter().enumerate() {
+                        let parameter_format =
+                            parameter.0.get_annotated_format_specifier(config, parameter.2);
+
+                        if i != 0 {
+                            format.push_str(", ");
+                        }
+                        format.push_str(parameter_format);
+
+                        // Formats without conversions, like the one of structures, take no argument.
+                        if !parameter_format.contains('%') {
+                            continue;
+                        }
+
+                        arguments.push_str(", ");
+                        parameter.0.write_annotated_format_argument(
+                            &mut arguments,
+                            &parameter.1,
+                            config,
+                            parameter.2,
+                        )?;
+                    }
+                }
+                TemplatePart::Result => {
+                    if let Some((result_format, result)) = result {
+                        format.push_str(result_format);
+
+                        if let Some(result) = result.filter(|_| result_format.contains('%')) {
+                            arguments.push_str(", ");
+                            self.return_type
+                                .write_format_argument(&mut arguments, result, config)?;
+                        }
+                    }
+                }
+                TemplatePart::Pid => {
+                    format.push_str("%d");
+                    arguments.push_str(", (int)getpid()");
+                }
+                TemplatePart::Tid => {
+                    format.push_str("%ld");
+                    arguments.push_str(", (long)syscall(SYS_gettid)");
+                }
+            }
+        }
+
+        let print = if self.logs_raw(config) {
+            "ldpsc_raw_log"
+        } else {
+            "fprintf"
+        };
+
+        let buffers: Vec<_> = self
+            .parameters
+            .iter()
+            .filter(|_| config.hexdump_buffers.is_some())
+            .filter_map(|parameter| parameter.3.as_ref().map(|length| (&parameter.1, length)))
+            .collect();
+
+        if buffers.is_empty() {
+            return writeln!(f, "    {}(output, \"{}\\n\"{});", print, format, arguments);
+        }
+
+        writeln!(f, "    {}(output, \"{}\"{});", print, format, arguments)?;
+
+        for (buffer, length) in buffers {
+            writeln!(f, "    {}(output, \" {}=\");", print, buffer)?;
+            writeln!(
+                f,
+                "    for (size_t offset = 0; {} && offset < (size_t)({}) && offset < {}; \
+                 offset++) {{",
+                buffer,
+                length,
+                config.hexdump_buffers.unwrap_or_default()
+            )?;
+            writeln!(
+                f,
+                "        {}(output, \"%02x\", ((const unsigned char *){})[offset]);",
+                print, buffer
+            )?;
+            writeln!(f, "    }}")?;
+        }
+
+        writeln!(f, "    {}(output, \"\\n\");", print)
+    }
+
+    /// Returns whether the results of this function are logged.
+    fn logs_result(&self, config: &Config) -> bool {
+        !self.return_type.is_void() && !config.no_result_functions.contains(&self.name)
+    }
+
+    /// Returns whether this stub logs with system calls, because the logging code calls it.
+    fn logs_raw(&self, config: &Config) -> bool {
+        logging_functions(config).contains(&&self.name[..])
+    }
+
+    /// Returns whether the text output needs to be flushed before calling the original function.
+    fn flushes_output(&self, config: &Config) -> bool {
+        config.log_format == LogFormat::Text
+            && (config.debug_output != "-" || config.per_thread_log.is_some())
+            && !self.logs_raw(config)
+    }
+
+    /// Returns whether the text output is flushed after each log line.
+    fn flushes_log(&self, config: &Config) -> bool {
+        config.flush && config.log_format == LogFormat::Text && !self.logs_raw(config)
+    }
+
+    /// Returns the template of the log lines of this function.
+    fn log_template(&self, config: &Config, has_result: bool) -> Vec<TemplatePart> {
+        if let Some(template) = &config.template {
+            return template.clone();
+        }
+
+        let mut template = Vec::new();
+
+        if has_result {
+            template.push(TemplatePart::Result);
+            template.push(TemplatePart::Text(" = ".to_string()));
+        }
+
+        template.push(TemplatePart::Function);
+        template.push(TemplatePart::Text("(".to_string()));
+        template.push(TemplatePart::Arguments);
+        template.push(TemplatePart::Text(")".to_string()));
+
+        template
+    }
+}
+
+/// Returns the functions the logging code calls with the given configuration.
+///
+/// The stubs of these functions log with system calls, since they would call themselves otherwise.
+fn logging_functions(config: &Config) -> Vec<&'static str> {
+    let mut functions = match config.log_format {
+        LogFormat::Text => vec!["fprintf", "fwrite"],
+        LogFormat::Binary => vec!["write", "dprintf"],
+    };
+
+    if config.per_thread_log.is_some() {
+        functions.extend_from_slice(&["fopen", "fflush", "snprintf"]);
+    } else if config.debug_output != "-" {
+        match config.log_format {
+            LogFormat::Text => functions.extend_from_slice(&["fopen", "fflush", "fclose"]),
+            LogFormat::Binary => functions.extend_from_slice(&["open", "close"]),
+        }
+    }
+
+    if config.record.is_some() {
+        functions.extend_from_slice(&["open", "writev", "close"]);
+    }
+
+    functions
+}
+
+/// Returns whether the calls are numbered in the log lines.
+fn logs_sequence(config: &Config) -> bool {
+    config.sequence && config.log_format == LogFormat::Text
+}
+
+/// Writes the statement opening the debug output as `output`.
+///
+/// Raw outputs are file descriptors opened with a system call, since the stub may be `open`.
+fn write_open_output(f: &mut dyn Write, config: &Config, raw: bool) -> fmt::Result {
+    if raw {
+        if config.debug_output == "-" {
+            writeln!(f, "    int output = STDERR_FILENO;")
+        } else {
+            writeln!(
+                f,
+                "    int output = (int)syscall(SYS_openat, AT_FDCWD, \"{}\", \
+                 O_WRONLY | O_CREAT | O_APPEND, 0644);",
+                escape_string_literal(&config.debug_output)
+            )
+        }
+    } else if config.log_format == LogFormat::Binary {
+        if config.debug_output == "-" {
+            writeln!(f, "    int output = STDERR_FILENO;")
+        } else {
+            writeln!(
+                f,
+                "    int output = open(\"{}\", O_WRONLY | O_CREAT | O_APPEND, 0644);",
+                escape_string_literal(&config.debug_output)
+            )
+        }
+    } else if config.per_thread_log.is_some() {
+        writeln!(f, "    FILE *output = ldpsc_thread_output();")
+    } else if config.debug_output == "-" {
+        writeln!(f, "    FILE *output = stderr;")
+    } else {
+        writeln!(
+            f,
+            "    FILE *output = fopen(\"{}\", \"a\");",
+            escape_string_literal(&config.debug_output)
+        )
+    }
+}
+
+/// Writes the statement closing the debug output opened by `write_open_output`.
+///
+/// Per thread outputs stay open for later calls of the same thread.
+fn write_close_output(f: &mut dyn Write, config: &Config, raw: bool) -> fmt::Result {
+    if raw {
+        if config.debug_output != "-" {
+            writeln!(f, "    syscall(SYS_close, output);")?;
+        }
+    } else if config.per_thread_log.is_some() {
+        writeln!(f, "    fflush(output);")?;
+    } else if config.debug_output != "-" && config.log_format == LogFormat::Binary {
+        writeln!(f, "    close(output);")?;
+    } else if config.debug_output != "-" {
+        writeln!(f, "    fclose(output);")?;
+    }
+
+    Ok(())
+}
+
+/// Writes a constructor that logs a line marking the start of the program.
+fn write_banner(f: &mut dyn Write, config: &Config) -> fmt::Result {
+    writeln!(f, "__attribute__((constructor))")?;
+    writeln!(f, "static void ldpsc_banner(void) {{")?;
+    write_open_output(f, config, false)?;
+    writeln!(
+        f,
+        "    fprintf(output, \"=== ldpsc start pid=%d ===\\n\", (int)getpid());"
+    )?;
+    write_close_output(f, config, false)?;
+    writeln!(f, "}}")
+}
+
+/// Writes the functions opening one debug output file per thread in the given directory.
+///
+/// The file is opened on the first call of each thread, falling back to stderr on errors.
+fn write_thread_output(f: &mut dyn Write, dir: &str) -> fmt::Result {
+    writeln!(f, "static __thread FILE *ldpsc_output;")?;
+    writeln!(f)?;
+    writeln!(f, "__attribute__((constructor))")?;
+    writeln!(f, "static void ldpsc_create_log_dir(void) {{")?;
+    writeln!(f, "    mkdir(\"{}\", 0777);", escape_string_literal(dir))?;
+    writeln!(f, "}}")?;
+    writeln!(f)?;
+    writeln!(f, "static FILE *ldpsc_thread_output(void) {{")?;
+    writeln!(f, "    if (!ldpsc_output) {{")?;
+    writeln!(f, "        char path[PATH_MAX];")?;
+    writeln!(
+        f,
+        "        snprintf(path, sizeof(path), \"{}/thread-%ld.log\", (long)syscall(SYS_gettid));",
+        escape_format_string(dir)
+    )?;
+    writeln!(f, "        ldpsc_output = fopen(path, \"a\");")?;
+    writeln!(f, "    }}")?;
+    writeln!(f, "    return ldpsc_output ? ldpsc_output : stderr;")?;
+    writeln!(f, "}}")
+}
+
+/// Writes the function logging with the `write` system call instead of the C library.
+///
+/// Lines longer than the buffer are truncated.
+fn write_raw_log(f: &mut dyn Write) -> fmt::Result {
+    writeln!(f, "static void ldpsc_raw_log(int fd, const char *format, ...) {{")?;
+    writeln!(f, "    char line[4096];")?;
+    writeln!(f, "    va_list arguments;")?;
+    writeln!(f, "    va_start(arguments, format);")?;
+    writeln!(
+        f,
+        "    int length = vsnprintf(line, sizeof(line), format, arguments);"
+    )?;
+    writeln!(f, "    va_end(arguments);")?;
+    writeln!(f, "    if (length > (int)sizeof(line) - 1) {{")?;
+    writeln!(f, "        length = sizeof(line) - 1;")?;
+    writeln!(f, "    }}")?;
+    writeln!(f, "    if (length > 0) {{")?;
+    writeln!(f, "        syscall(SYS_write, fd, line, (size_t)length);")?;
+    writeln!(f, "    }}")?;
+    writeln!(f, "}}")
+}
+
+/// Writes the function storing a value in a word of a binary log record.
+///
+/// Values larger than a word are truncated.
+fn write_store_word(f: &mut dyn Write) -> fmt::Result {
+    writeln!(
+        f,
+        "static void ldpsc_store_word(uint64_t *word, const void *value, size_t size) {{"
+    )?;
+    writeln!(
+        f,
+        "    memcpy(word, value, size < sizeof(*word) ? size : sizeof(*word));"
+    )?;
+    writeln!(f, "}}")
+}
+
+/// Writes a constructor that seeds the random number generator used for failure rates.
+fn write_seed(f: &mut dyn Write) -> fmt::Result {
+    writeln!(f, "__attribute__((constructor))")?;
+    writeln!(f, "static void ldpsc_seed(void) {{")?;
+    writeln!(f, "    srand(time(NULL) ^ getpid());")?;
+    writeln!(f, "}}")
+}
+
+/// Escapes the given text to be used literally in the format string of `fprintf`.
+fn escape_format_string(text: &str) -> String {
+    escape_string_literal(text).replace('%', "%%")
+}
+
+/// Escapes the given text to be used in a C string literal.
+fn escape_string_literal(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let mut buffer = [0; 4];
+                for byte in c.encode_utf8(&mut buffer).bytes() {
+                    escaped.push_str(&format!("\\{:03o}", byte));
+                }
+            }
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Parses a C function.
 ///
 /// # Note
 /// This does not yet correspond to the C standard and just supports a subset of possible functions.
 named!(function<&[u8], Function>,
+    terminated!(
+        function_declarator,
+        function_terminator
+    )
+);
+
+/// Parses the `;` ending a function declaration.
+named!(function_terminator<&[u8], char>,
+    preceded!(
+        opt!(multispace),
+        char!(';')
+    )
+);
+
+/// Parses a C function without the `;` ending its declaration.
+named!(function_declarator<&[u8], Function>,
     do_parse!(
+        internal: many0!(
+            delimited!(
+                opt!(multispace),
+                storage_specifier,
+                multispace
+            )
+        ) >>
         return_type: parse_type >>
         opt!(multispace) >>
         name: map!(
@@ -253,94 +2525,2316 @@ named!(function<&[u8], Function>,
             |ident| from_utf8(ident).unwrap().to_string()
         ) >>
         opt!(multispace) >>
+        unprototyped: opt!(
+            peek!(
+                tuple!(
+                    char!('('),
+                    opt!(multispace),
+                    char!(')')
+                )
+            )
+        ) >>
         parameters: delimited!(
             char!('('),
             ws!(
-                separated_list!(
-                    ws!(
-                        tag!(",")
-                    ),
-                    pair!(
-                        parse_type,
+                alt!(
+                    value!(
+                        (Vec::new(), true),
+                        tag!("...")
+                    ) |
+                    value!(
+                        (Vec::new(), false),
+                        terminated!(
+                            tag!("void"),
+                            peek!(
+                                preceded!(
+                                    opt!(multispace),
+                                    char!(')')
+                                )
+                            )
+                        )
+                    ) |
+                    tuple!(
+                        separated_list!(
+                            ws!(
+                                tag!(",")
+                            ),
+                            tuple!(
+                                parse_type,
+                                map!(
+                                    identifier,
+                                    |ident| from_utf8(ident).unwrap().to_string()
+                                ),
+                                opt!(array_declarator),
+                                opt!(block_comment)
+                            )
+                        ),
                         map!(
-                            identifier,
-                            |ident| from_utf8(ident).unwrap().to_string()
+                            opt!(
+                                preceded!(
+                                    ws!(
+                                        tag!(",")
+                                    ),
+                                    ws!(
+                                        tag!("...")
+                                    )
+                                )
+                            ),
+                            |ellipsis| ellipsis.is_some()
+                        )
+                    )
+                )
+            ),
+            char!(')')
+        ) >>
+        (Function {
+            return_type,
+            name,
+            variadic: parameters.1,
+            unprototyped: unprototyped.is_some(),
+            parameters: parameters.0
+                .into_iter()
+                .map(|(parameter_type, name, array, comment)| (
+                    match array {
+                        Some((is_static, qualifiers, size)) => {
+                            parameter_type.into_array(is_static, qualifiers, size)
+                        }
+                        None => parameter_type,
+                    },
+                    name,
+                    comment.and_then(format_annotation),
+                    comment.and_then(length_annotation)
+                ))
+                .collect(),
+            library: None,
+            id: 0,
+            source: String::new(),
+            internal: internal.contains(&true),
+            va_list_variant: None,
+        })
+    )
+);
+
+/// Parses the brackets of a parameter declared as an array.
+///
+/// Returns whether the array is declared `static`, its qualifiers and its size, which may be
+/// empty.
+named!(array_declarator<&[u8], (bool, Vec<TypeQualifier>, &str)>,
+    do_parse!(
+        char!('[') >>
+        opt!(multispace) >>
+        leading_static: opt!(terminated!(static_keyword, opt!(multispace))) >>
+        qualifiers: many0!(terminated!(type_qualifier, opt!(multispace))) >>
+        trailing_static: opt!(terminated!(static_keyword, opt!(multispace))) >>
+        size: map_res!(take_until!("]"), from_utf8) >>
+        char!(']') >>
+        (leading_static.is_some() || trailing_static.is_some(), qualifiers, size.trim())
+    )
+);
+
+/// Parses the `static` keyword.
+named!(static_keyword,
+    terminated!(
+        tag!("static"),
+        not!(
+            alt!(
+                identifier_nondigit |
+                digit
+            )
+        )
+    )
+);
+
+/// Parses the start of a function returning a function pointer, returning the function name.
+///
+/// These are declared like `void (*signal(int sig, void (*handler)(int)))(int);`.
+named!(function_pointer_return<&[u8], String>,
+    do_parse!(
+        parse_type >>
+        opt!(multispace) >>
+        char!('(') >>
+        opt!(multispace) >>
+        char!('*') >>
+        opt!(multispace) >>
+        name: map!(
+            identifier,
+            |ident| from_utf8(ident).unwrap().to_string()
+        ) >>
+        opt!(multispace) >>
+        char!('(') >>
+        (name)
+    )
+);
+
+/// Parses a block comment, returning its text.
+named!(block_comment,
+    delimited!(
+        tag!("/*"),
+        take_until!("*/"),
+        tag!("*/")
+    )
+);
+
+/// Parses a line comment, returning its text.
+named!(line_comment,
+    preceded!(
+        opt!(multispace),
+        delimited!(
+            tag!("//"),
+            take_until!("\n"),
+            char!('\n')
+        )
+    )
+);
+
+/// Parses a storage class or function specifier of a declaration.
+///
+/// The result is true, if the specifier prevents interposing the function.
+named!(storage_specifier<&[u8], bool>,
+    terminated!(
+        alt!(
+            value!(
+                false,
+                tag!("extern")
+            ) |
+            value!(
+                true,
+                tag!("static")
+            ) |
+            value!(
+                true,
+                tag!("inline")
+            )
+        ),
+        not!(
+            alt!(
+                identifier_nondigit |
+                digit
+            )
+        )
+    )
+);
+
+/// Parses a C type qualifier.
+named!(type_qualifier<&[u8], TypeQualifier>,
+    terminated!(
+        alt!(
+            value!(
+                TypeQualifier::Const,
+                tag!("const")
+            ) |
+            value!(
+                TypeQualifier::Restrict,
+                alt!(
+                    tag!("restrict") |
+                    tag!("__restrict__") |
+                    tag!("__restrict")
+                )
+            ) |
+            value!(
+                TypeQualifier::Volatile,
+                tag!("volatile")
+            ) |
+            value!(
+                TypeQualifier::Atomic,
+                terminated!(
+                    tag!("_Atomic"),
+                    not!(preceded!(opt!(multispace), char!('(')))
+                )
+            )
+        ),
+        not!(
+            alt!(
+                identifier_nondigit |
+                digit
+            )
+        )
+    )
+);
+
+/// Parses a keyword of a C type specifier.
+named!(type_specifier,
+    terminated!(
+        call!(specifier_keyword),
+        not!(
+            alt!(
+                identifier_nondigit |
+                digit
+            )
+        )
+    )
+);
+
+/// Parses one of the `SPECIFIER_KEYWORDS`.
+///
+/// Like `tag!`, this needs more input if the input is a prefix of a keyword.
+/// The longest matching keyword is used, so `int8_t` is not parsed as `int`.
+fn specifier_keyword(input: &[u8]) -> nom::IResult<&[u8], &[u8]> {
+    let mut longest: Option<&[u8]> = None;
+
+    for keyword in SPECIFIER_KEYWORDS {
+        let keyword = keyword.as_bytes();
+
+        if input.starts_with(keyword) {
+            if longest.is_none_or(|longest| keyword.len() > longest.len()) {
+                longest = Some(keyword);
+            }
+        } else if keyword.starts_with(input) {
+            return Err(nom::Err::Incomplete(nom::Needed::Size(keyword.len())));
+        }
+    }
+
+    match longest {
+        Some(keyword) => Ok((&input[keyword.len()..], &input[..keyword.len()])),
+        None => Err(nom::Err::Error(error_position!(input, nom::ErrorKind::Alt))),
+    }
+}
+
+/// Parses the `_Atomic(type)` form of a type specifier, returning the keywords of the inner type.
+named!(atomic_type_specifier<&[u8], Vec<&[u8]>>,
+    delimited!(
+        tuple!(
+            opt!(multispace),
+            tag!("_Atomic"),
+            opt!(multispace),
+            char!('(')
+        ),
+        many1!(
+            ws!(type_specifier)
+        ),
+        char!(')')
+    )
+);
+
+/// Parses a C type.
+///
+/// # Note
+/// This does not yet correspond to the C standard and just supports a subset of possible types.
+named!(parse_type<&[u8], Type>,
+    map!(
+        tuple!(
+            many0!(
+                delimited!(
+                    opt!(multispace),
+                    type_qualifier,
+                    multispace
+                )
+            ),
+            alt!(
+                map!(atomic_type_specifier, |specifier| (true, specifier)) |
+                map!(many1!(ws!(type_specifier)), |specifier| (false, specifier))
+            ),
+            many0!(
+                preceded!(
+                    opt!(multispace),
+                    type_qualifier
+                )
+            ),
+            many0!(
+                preceded!(
+                    preceded!(
+                        opt!(multispace),
+                        tag!("*")
+                    ),
+                    many0!(
+                        preceded!(
+                            opt!(multispace),
+                            type_qualifier
                         )
                     )
                 )
+            )
+        )
+    ,
+    |(mut qualifiers, (atomic, specifier), trailing_qualifiers, pointers)| {
+            // `_Atomic(int)` is the same type as `_Atomic int`, which is how it is written back.
+            if atomic {
+                qualifiers.push(TypeQualifier::Atomic);
+            }
+
+            // Likewise, `void const` is written back as `const void`.
+            qualifiers.extend(trailing_qualifiers);
+
+            Type::new(
+                qualifiers,
+                specifier
+                    .into_iter()
+                    .map(|keyword| from_utf8(keyword).expect("nom bug"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                pointers
+            )
+        }
+    )
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_config;
+
+    #[test]
+    fn test_transform_empty_file() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        let mut diagnostics = Diagnostics::new(&mut output, &config);
+
+        assert_eq!(
+            transform_file(b"", &config, &mut diagnostics),
+            Err("No function prototypes found in input".into())
+        );
+        assert_eq!(
+            transform_file(b" \n\t  \n", &config, &mut diagnostics),
+            Err("No function prototypes found in input".into())
+        );
+    }
+
+    #[test]
+    fn test_transform_verbose() {
+        let input = b"int puts(const char *s);\nvoid free(void *ptr);\n";
+
+        let config = parse_config(["ldpsc", "-c", "--verbose"]).unwrap();
+        let mut output = Vec::new();
+        transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert_eq!(output, b"ldpsc: parsed 2 function(s)\n");
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert_eq!(output, b"");
+    }
+
+    #[test]
+    fn test_transform_duplicate() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+
+        let transformed = transform_file(
+            b"int puts(const char *s);\nint puts(const char *str);\n",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+
+        assert_eq!(transformed.matches("int puts(").count(), 1);
+        assert_eq!(
+            output,
+            &b"ldpsc: warning: skipping duplicate declaration of 'puts'\n"[..]
+        );
+    }
+
+    #[test]
+    fn test_transform_conflicting_duplicate() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+
+        assert_eq!(
+            transform_file(
+                b"int puts(const char *s);\nint puts(char *s);\n",
+                &config,
+                &mut Diagnostics::new(&mut output, &config),
+            ),
+            Err("Conflicting declarations of 'puts'".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_pointer_qualifiers() {
+        let (_, pointer_to_const) = parse_type(b"const char *p").unwrap();
+        assert_eq!(pointer_to_const.qualifiers, vec![TypeQualifier::Const]);
+        assert_eq!(pointer_to_const.pointers, vec![vec![]]);
+        assert_eq!(pointer_to_const.to_string(), "const char *");
+
+        let (_, const_pointer) = parse_type(b"char * const p").unwrap();
+        assert_eq!(const_pointer.qualifiers, vec![]);
+        assert_eq!(const_pointer.pointers, vec![vec![TypeQualifier::Const]]);
+        assert_eq!(const_pointer.to_string(), "char *const");
+
+        let (_, const_pointer_to_const) = parse_type(b"const char *const p").unwrap();
+        assert_eq!(const_pointer_to_const.qualifiers, vec![TypeQualifier::Const]);
+        assert_eq!(
+            const_pointer_to_const.pointers,
+            vec![vec![TypeQualifier::Const]]
+        );
+        assert_eq!(const_pointer_to_const.to_string(), "const char *const");
+
+        let (_, nested) = parse_type(b"int *const volatile*p").unwrap();
+        assert_eq!(nested.to_string(), "int *const volatile *");
+
+        let (rest, constant) = parse_type(b"char *constant").unwrap();
+        assert_eq!(constant.pointers, vec![vec![]]);
+        assert_eq!(rest, b"constant");
+    }
+
+    #[test]
+    fn test_signature_pointer_qualifiers() {
+        let (_, function) = function(
+            b"const char *const f(const char *a, char * const b, const char * const c);",
+        ).unwrap();
+
+        let mut signature = String::new();
+        function.get_signature(&mut signature, None).unwrap();
+        assert_eq!(
+            signature,
+            "const char *const f(const char *a, char *const b, const char *const c)"
+        );
+
+        let mut signature = String::new();
+        function.get_signature(&mut signature, Some("original_f")).unwrap();
+        assert_eq!(
+            signature,
+            "const char *(*original_f)(const char *a, char *const b, const char *const c)"
+        );
+    }
+
+    #[test]
+    fn test_pointer_format() {
+        let (_, function) = function(b"void *memchr(const void *s, int c, size_t n);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("\"%p = memchr(%p, %d, %zu)\\n\", result, s, c, n);"));
+
+        let config = parse_config(["ldpsc", "-c", "--pointer-format", "hex"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "\"0x%016lx = memchr(0x%016lx, %d, %zu)\\n\", \
+             (unsigned long)(uintptr_t)result, (unsigned long)(uintptr_t)s, c, n);"
+        ));
+
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"void *memchr(const void *s, int c, size_t n);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<stdint.h>\n"));
+    }
+
+    #[test]
+    fn test_log_point() {
+        let (_, function) = function(b"int puts(const char *s);").unwrap();
+        let entry_log = "    fprintf(output, \"puts(\\\"%s\\\")\\n\", (s ? s : \"(null)\"));\n";
+        let exit_log =
+            "    fprintf(output, \"%d = puts(\\\"%s\\\")\\n\", result, (s ? s : \"(null)\"));\n";
+        let call = "    int result = original_puts(s);\n";
+
+        let config = parse_config(["ldpsc", "-c", "--log-point", "entry"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.find(entry_log).unwrap() < definition.find(call).unwrap());
+        assert!(!definition.contains(exit_log));
+
+        let config = parse_config(["ldpsc", "-c", "--log-point", "exit"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(!definition.contains(entry_log));
+        assert!(definition.find(call).unwrap() < definition.find(exit_log).unwrap());
+
+        let config = parse_config(["ldpsc", "-c", "--log-point", "both"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.find(entry_log).unwrap() < definition.find(call).unwrap());
+        assert!(definition.find(call).unwrap() < definition.find(exit_log).unwrap());
+    }
+
+    #[test]
+    fn test_bool_format() {
+        let (_, function) = function(b"_Bool isready(_Bool flag);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("\"%d = isready(%d)\\n\", result, flag);"));
+
+        let config = parse_config(["ldpsc", "-c", "--bool-names"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "\"%s = isready(%s)\\n\", \
+             (result ? \"true\" : \"false\"), (flag ? \"true\" : \"false\"));"
+        ));
+    }
+
+    #[test]
+    fn test_escape_format_string() {
+        assert_eq!(escape_format_string("[app] "), "[app] ");
+        assert_eq!(escape_format_string("100%"), "100%%");
+        assert_eq!(escape_format_string("\"q\" \\"), "\\\"q\\\" \\\\");
+        assert_eq!(escape_format_string("a\nb\tc\x07"), "a\\nb\\tc\\007");
+    }
+
+    #[test]
+    fn test_log_prefix() {
+        let (_, function) = function(b"int abs(int j);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c", "--log-prefix", "50% \"x\": "]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "    fprintf(output, \"50%% \\\"x\\\": %d = abs(%d)\\n\", result, j);\n"
+        ));
+    }
+
+    #[test]
+    fn test_non_interposable() {
+        let input = b"int main(int argc, char **argv);\nint puts(const char *s);\n";
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        assert!(transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).is_ok());
+        assert_eq!(output, &b"ldpsc: warning: 'main' cannot be interposed\n"[..]);
+
+        let config = parse_config(["ldpsc", "-c", "--strict"]).unwrap();
+        let mut output = Vec::new();
+        assert_eq!(
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)),
+            Err("'main' cannot be interposed".into())
+        );
+    }
+
+    #[test]
+    fn test_alias() {
+        let (_, foo) = function(b"int foo(int x);").unwrap();
+        let (_, baz) = function(b"int baz(int x);").unwrap();
+        let config = parse_config(["ldpsc", "-c", "--alias", "foo=bar"]).unwrap();
+
+        let mut definition = String::new();
+        foo.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.starts_with("int foo(int x) {\n"));
+        assert!(definition.contains(")dlsym(RTLD_NEXT, \"bar\"), __ATOMIC_RELEASE);\n"));
+
+        let mut definition = String::new();
+        baz.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(")dlsym(RTLD_NEXT, \"baz\"), __ATOMIC_RELEASE);\n"));
+    }
+
+    #[test]
+    fn test_wchar() {
+        let (_, function) = function(b"wchar_t *wcscpy(wchar_t *dest, const wchar_t *src);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.starts_with("wchar_t *wcscpy(wchar_t *dest, const wchar_t *src) {\n"));
+        assert!(definition.contains(
+            "\"\\\"%ls\\\" = wcscpy(\\\"%ls\\\", \\\"%ls\\\")\\n\", \
+             (result ? result : L\"(null)\"), (dest ? dest : L\"(null)\"), (src ? src : L\"(null)\"));"
+        ));
+
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"size_t wcslen(const wchar_t *s);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<wchar.h>\n"));
+
+        let transformed = transform_file(
+            b"size_t strlen(const char *s);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(!transformed.contains("#include<wchar.h>\n"));
+    }
+
+    #[test]
+    fn test_log_banner() {
+        let input = b"int puts(const char *s);";
+        let banner = "__attribute__((constructor))\n\
+                      static void ldpsc_banner(void) {\n    \
+                      FILE *output = fopen(\"log.txt\", \"a\");\n    \
+                      fprintf(output, \"=== ldpsc start pid=%d ===\\n\", (int)getpid());\n    \
+                      fclose(output);\n\
+                      }\n";
+
+        let config = parse_config(["ldpsc", "-c", "-d", "log.txt", "--log-banner"]).unwrap();
+        let mut output = Vec::new();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(transformed.contains("#include<unistd.h>\n"));
+        assert!(transformed.contains(banner));
+
+        let config = parse_config(["ldpsc", "-c", "-d", "log.txt"]).unwrap();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(!transformed.contains("ldpsc_banner"));
+    }
+
+    #[test]
+    fn test_fail() {
+        let input = b"int open(const char *path, int flags);\nvoid free(void *ptr);";
+
+        let config =
+            parse_config(["ldpsc", "-c", "--fail", "open=-1:2", "--fail", "free=:EINVAL"]).unwrap();
+        let mut output = Vec::new();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(transformed.contains("#include<errno.h>\n"));
+        assert!(transformed.contains(
+            "    fprintf(output, \"%d = open(\\\"%s\\\", %d)\\n\", (int)(-1), (path ? path : \"(null)\"), flags);\n    \
+             errno = 2;\n    \
+             return -1;\n}"
+        ));
+        assert!(transformed.contains("    errno = EINVAL;\n}"));
+        assert!(!transformed.contains("original_open"));
+        assert!(!transformed.contains("original_free"));
+
+        let config = parse_config(["ldpsc", "-c", "--fail", "open=:2"]).unwrap();
+        assert!(transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).is_err());
+
+        let config = parse_config(["ldpsc", "-c", "--fail", "free=0:2"]).unwrap();
+        assert!(transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).is_err());
+
+        let config = parse_config(["ldpsc", "-c", "--fail", "open=notanumber:2"]).unwrap();
+        assert_eq!(
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)),
+            Err("'open' returns int and cannot fail with 'notanumber', \
+                 expected an integer or a character constant"
+                .into())
+        );
+
+        assert!(parse_config(["ldpsc", "-c", "--fail", "open=-1"]).is_err());
+        assert!(parse_config(["ldpsc", "-c", "--fail", "open=-1:"]).is_err());
+        assert!(parse_config(["ldpsc", "-c", "--fail", "open=-1:2+"]).is_err());
+    }
+
+    #[test]
+    fn test_fail_rate() {
+        let input = b"void *malloc(size_t size);";
+
+        let config = parse_config([
+            "ldpsc",
+            "-c",
+            "--fail",
+            "malloc=NULL:ENOMEM",
+            "--fail-rate",
+            "malloc=10",
+        ]).unwrap();
+        let mut output = Vec::new();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(transformed.contains("    srand(time(NULL) ^ getpid());\n"));
+        assert!(transformed.contains(
+            "    if (rand() % 100 < 10) {\n        \
+             fprintf(output, \"%p = malloc(%zu)\\n\", (void *)(NULL), size);\n        \
+             errno = ENOMEM;\n        \
+             return NULL;\n    \
+             }\n    \
+             static void *(*original_malloc)(size_t size);\n"
+        ));
+
+        let config = parse_config(["ldpsc", "-c", "--fail-rate", "malloc=10"]).unwrap();
+        assert!(transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).is_err());
+
+        assert!(parse_config(["ldpsc", "-c", "--fail-rate", "malloc=100"]).is_ok());
+        assert!(parse_config(["ldpsc", "-c", "--fail-rate", "malloc=101"]).is_err());
+        assert!(parse_config(["ldpsc", "-c", "--fail-rate", "malloc=-1"]).is_err());
+        assert!(parse_config(["ldpsc", "-c", "--fail-rate", "malloc"]).is_err());
+    }
+
+    #[test]
+    fn test_multi_word_specifiers() {
+        let (_, unsigned_long) = parse_type(b"unsigned  long\tint *p").unwrap();
+        assert_eq!(unsigned_long.specifier, "unsigned long int");
+        assert_eq!(unsigned_long.to_string(), "unsigned long int *");
+
+        let (rest, integer) = parse_type(b"int integer").unwrap();
+        assert_eq!(integer.specifier, "int");
+        assert_eq!(rest, b"integer");
+    }
+
+    #[test]
+    fn test_floating_types() {
+        let (_, function) =
+            function(b"double _Complex cmul(double _Complex a, double _Complex b);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "\"%f+%fi = cmul(%f+%fi, %f+%fi)\\n\", \
+             creal(result), cimag(result), creal(a), cimag(a), creal(b), cimag(b));"
+        ));
+
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"long double fabsl(long double x);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("\"%Lf = fabsl(%Lf)\\n\", result, x);"));
+        assert!(!transformed.contains("#include<complex.h>\n"));
+
+        let transformed = transform_file(
+            b"float crealf(float _Complex z);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<complex.h>\n"));
+        assert!(transformed.contains("\"%f = crealf(%f+%fi)\\n\", result, crealf(z), cimagf(z));"));
+    }
+
+    #[test]
+    fn test_list_functions() {
+        let config = parse_config(["ldpsc", "--list"]).unwrap();
+        let mut output = Vec::new();
+        let list = list_functions(
+            b"int puts(const char *s);\nvoid free(void *ptr);\nchar *strcpy(char *dest, const char *src);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert_eq!(list, "puts\t1\tint\nfree\t1\tvoid\nstrcpy\t2\tchar *\n");
+    }
+
+    #[test]
+    fn test_transform_file_to() {
+        let input = b"int puts(const char *s);\nvoid *malloc(size_t size);";
+        let config = parse_config(["ldpsc", "-c", "--log-banner", "--log-point", "both"]).unwrap();
+        let mut output = Vec::new();
+
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        let mut streamed = Vec::new();
+        transform_file_to(
+            input,
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+            &mut streamed,
+        ).unwrap();
+        assert_eq!(streamed, transformed.as_bytes());
+    }
+
+    #[test]
+    fn test_duplicate_parameter_names() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        assert_eq!(
+            transform_file(
+                b"int f(int x, char *y, int x);",
+                &config,
+                &mut Diagnostics::new(&mut output, &config)
+            ),
+            Err("The parameter 'x' of 'f' is declared more than once".into())
+        );
+    }
+
+    #[test]
+    fn test_restrict() {
+        let (_, restrict_pointer) = parse_type(b"char * restrict p").unwrap();
+        assert_eq!(restrict_pointer.to_string(), "char *restrict");
+
+        let (_, misplaced) = parse_type(b"const restrict char **p").unwrap();
+        assert_eq!(misplaced.to_string(), "const char **restrict");
+
+        let (_, function) = function(b"void f(char * restrict p);").unwrap();
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.starts_with("void f(char *restrict p) {\n"));
+        assert!(definition.contains("    static void (*original_f)(char *restrict p);\n"));
+    }
+
+    #[test]
+    fn test_lenient() {
+        let input = b"extern int counter;\n\
+                      struct point {\n    int x;\n    int y;\n} origin;\n\
+                      int puts(const char *s);\n\
+                      static int add(int a, int b) { return a + b; }\n";
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        assert!(transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).is_err());
+
+        let config = parse_config(["ldpsc", "-c", "--lenient", "--verbose"]).unwrap();
+        let mut output = Vec::new();
+        let list =
+            list_functions(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert_eq!(list, "puts\t1\tint\n");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "ldpsc: skipping 'extern int counter;'\n\
+             ldpsc: skipping 'struct point { int x; int y; }'\n\
+             ldpsc: skipping 'origin;'\n\
+             ldpsc: skipping 'static int add(int a, int b) { return a + b; }'\n\
+             ldpsc: warning: skipped 4 unrecognized item(s)\n\
+             ldpsc: parsed 1 function(s)\n"
+        );
+
+        let config = parse_config(["ldpsc", "-c", "--lenient", "--quiet"]).unwrap();
+        let mut output = Vec::new();
+        list_functions(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert_eq!(output, b"");
+    }
+
+    #[test]
+    fn test_orig_prefix() {
+        let (_, function) = function(b"int foo(int x);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c", "--orig-prefix", "real_"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("    static int (*real_foo)(int x);\n"));
+        assert!(definition.contains("    int result = real_foo(x);\n"));
+        assert!(!definition.contains("original_"));
+
+        assert!(parse_config(["ldpsc", "-c", "--orig-prefix", ""]).is_err());
+        assert!(parse_config(["ldpsc", "-c", "--orig-prefix", "1st_"]).is_err());
+        assert!(parse_config(["ldpsc", "-c", "--orig-prefix", "real-"]).is_err());
+    }
+
+    #[test]
+    fn test_missing_semicolon() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        let expected = |line| {
+            Err(ParseError {
+                message: "Expected ';' after function declaration of 'foo'".to_string(),
+                location: Some((line, 1)),
+            })
+        };
+
+        assert_eq!(
+            transform_file(
+                b"int foo(int x)\nint bar(int y);",
+                &config,
+                &mut Diagnostics::new(&mut output, &config)
             ),
-            char!(')')
-        ) >>
-        opt!(multispace) >>
-        char!(';') >>
-        (Function {
-            return_type,
-            name,
-            parameters
-        })
-    )
-);
+            expected(1)
+        );
+        assert_eq!(
+            transform_file(
+                b"int bar(int y);\nint foo(int x)\n",
+                &config,
+                &mut Diagnostics::new(&mut output, &config)
+            ),
+            expected(2)
+        );
+    }
 
-/// Parses a C type.
-///
-/// # Note
-/// This does not yet correspond to the C standard and just supports a subset of possible types.
-named!(parse_type<&[u8], Type>,
-    map!(
-        tuple!(
-            many0!(
-                terminated!(
-                    alt!(
-                        value!(
-                            TypeQualifier::Const,
-                            tag!("const")
-                        ) |
-                        value!(
-                            TypeQualifier::Restrict,
-                            tag!("restrict")
-                        ) |
-                        value!(
-                            TypeQualifier::Volatile,
-                            tag!("volatile")
-                        ) |
-                        value!(
-                            TypeQualifier::Atomic,
-                            tag!("_Atomic")
-                        )
-                    ),
-                    multispace
-                )
+    #[test]
+    fn test_null_marker() {
+        let (_, function) = function(b"void *fopen(const char *path, const char *mode);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c", "--null-marker", "NULL"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "    if (result) {\n        \
+             fprintf(output, \"%p = fopen(\\\"%s\\\", \\\"%s\\\")\\n\", result, \
+             (path ? path : \"(null)\"), (mode ? mode : \"(null)\"));\n    \
+             } else {\n        \
+             fprintf(output, \"NULL = fopen(\\\"%s\\\", \\\"%s\\\")\\n\", \
+             (path ? path : \"(null)\"), (mode ? mode : \"(null)\"));\n    \
+             }\n"
+        ));
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(!definition.contains("if (result)"));
+    }
+
+    #[test]
+    fn test_list_supported_types() {
+        let config = parse_config(["ldpsc", "--list-supported-types"]).unwrap();
+        let list = list_supported_types(&config);
+        let lines: Vec<_> = list.lines().collect();
+        assert!(lines.contains(&"int\t%d"));
+        assert!(lines.contains(&"size_t\t%zu"));
+        assert!(lines.contains(&"ssize_t\t%zd"));
+        assert!(lines.contains(&"short\tfallback"));
+        assert!(lines.contains(&"void\tnot logged"));
+        assert!(lines.contains(&"long double\t%Lf"));
+        assert_eq!(lines.last(), Some(&"pointers\t%p"));
+
+        for keyword in SPECIFIER_KEYWORDS {
+            assert_eq!(
+                type_specifier(format!("{} x", keyword).as_bytes()),
+                Ok((&b" x"[..], keyword.as_bytes()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_result_declaration() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let declarations = [
+            ("char *f(int x);", "    char *result = original_f(x);\n"),
+            ("char **f(int x);", "    char **result = original_f(x);\n"),
+            ("const char *f(int x);", "    const char *result = original_f(x);\n"),
+            ("char *const f(int x);", "    char *const result = original_f(x);\n"),
+            ("const char *const *f(int x);", "    const char *const *result = original_f(x);\n"),
+            ("const int f(int x);", "    const int result = original_f(x);\n"),
+        ];
+
+        for (prototype, declaration) in &declarations {
+            let prototype = format!("\n{}", prototype);
+            let (_, function) = function(prototype.as_bytes()).unwrap();
+            let mut definition = String::new();
+            function.get_definition(&mut definition, &config).unwrap();
+            assert!(definition.contains(declaration), "{}", definition);
+        }
+    }
+
+    #[test]
+    fn test_library_annotation() {
+        let input = b"// lib: libssl\n\
+                      int SSL_read(void *ssl, void *buf, int num);\n\
+                      // Not an annotation.\n\
+                      int puts(const char *s);\n";
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(transformed.contains(
+            "        void *ldpsc_library_handle = dlopen(\"libssl.so\", RTLD_LAZY);\n        \
+             __atomic_store_n(&original_SSL_read, (int (*)(void *ssl, void *buf, int num))\
+             dlsym(ldpsc_library_handle ? ldpsc_library_handle : RTLD_NEXT, \"SSL_read\"), \
+             __ATOMIC_RELEASE);\n"
+        ));
+        assert!(transformed.contains(
+            "        __atomic_store_n(&original_puts, (int (*)(const char *s))dlsym(RTLD_NEXT, \"puts\"), \
+             __ATOMIC_RELEASE);\n"
+        ));
+
+        let input = b"// lib: /opt/\"quoted\"\\dir/libx.so\nint x(int a);";
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(transformed.contains(
+            "        void *ldpsc_library_handle = \
+             dlopen(\"/opt/\\\"quoted\\\"\\\\dir/libx.so\", RTLD_LAZY);\n"
+        ));
+
+        assert_eq!(library_annotation(b" lib: libc.so.6 "), Some("libc.so.6".to_string()));
+        assert_eq!(library_annotation(b" lib:"), None);
+        assert_eq!(library_annotation(b" library"), None);
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        let list = list_functions(
+            b"\tconst\tchar\t*\tconst\t*\tf\t(\tconst\tchar\t*\ts\t,\tint\tn\t)\t;\r\n\
+              \r\n\
+              int\r\n\
+              g\r\n\
+              (\r\n\
+              \tunsigned\r\n\
+              \tlong\r\n\
+              \t*\r\n\
+              \tconst\r\n\
+              \tn\r\n\
+              )\r\n\
+              ;\r\n",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert_eq!(list, "f\t2\tconst char *const *\ng\t1\tint\n");
+
+        assert_eq!(
+            transform_file(
+                b"int f(int n)\r\n\r\nint g(int n);\r\n",
+                &config,
+                &mut Diagnostics::new(&mut output, &config)
             ),
-            ws!(
-                alt!(
-                    tag!("void") |
-                    tag!("char") |
-                    tag!("short") |
-                    tag!("int") |
-                    tag!("long") |
-                    tag!("float") |
-                    tag!("double") |
-                    tag!("signed") |
-                    tag!("unsigned") |
-                    tag!("_Bool") |
-                    tag!("_Complex") |
-                    tag!("size_t")
-                )
+            Err(ParseError {
+                message: "Expected ';' after function declaration of 'f'".to_string(),
+                location: Some((1, 1)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_size_formats() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"size_t strlen(const char *s);\nssize_t read(int fd, void *buf, size_t count);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("\"%zu = strlen(\\\"%s\\\")\\n\""));
+        assert!(transformed.contains("\"%zd = read(%d, %p, %zu)\\n\""));
+        assert!(transformed.contains("#include<sys/types.h>\n"));
+    }
+
+    #[test]
+    fn test_type_headers() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"size_t strlen(const char *s);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<stddef.h>\n"));
+        assert!(!transformed.contains("#include<sys/types.h>\n"));
+
+        let transformed = transform_file(
+            b"off_t lseek(int fd, off_t offset, int whence);\n\
+              _Bool f(uint32_t a, int64_t b, uint8_t *c);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert_eq!(transformed.matches("#include<sys/types.h>\n").count(), 1);
+        assert_eq!(transformed.matches("#include<inttypes.h>\n").count(), 1);
+        assert!(transformed.contains("#include<stdbool.h>\n"));
+        assert!(!transformed.contains("#include<stddef.h>\n"));
+        assert!(transformed.contains("\"%jd = lseek(%d, %jd, %d)\\n\", (intmax_t)result, fd, (intmax_t)offset, whence);"));
+        assert!(transformed.contains("\"%d = f(%ju, %jd, %p)\\n\", result, (uintmax_t)a, (intmax_t)b, c);"));
+    }
+
+    #[test]
+    fn test_int_radix() {
+        let (_, function) =
+            function(b"unsigned long f(int flags, unsigned mode, long offset, size_t n);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("\"%lu = f(%d, %u, %ld, %zu)\\n\""));
+
+        let config = parse_config(["ldpsc", "-c", "--int-radix", "hex"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("\"0x%lx = f(0x%x, 0x%x, 0x%lx, 0x%zx)\\n\""));
+    }
+
+    #[test]
+    fn test_format_annotations() {
+        let (_, function) = function(
+            b"int f(int flags /*hex*/, int mode, int count /* dec */, void *name /*string*/, \
+              char *data /*pointer*/, int other /* unrelated */);",
+        ).unwrap();
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.starts_with(
+            "int f(int flags, int mode, int count, void *name, char *data, int other) {\n"
+        ));
+        assert!(definition.contains(
+            "\"%d = f(0x%x, %d, %d, \\\"%s\\\", %p, %d)\\n\", result, flags, mode, count, \
+             ((const char *)name ? (const char *)name : \"(null)\"), (void *)data, other);"
+        ));
+
+        let config = parse_config(["ldpsc", "-c", "--int-radix", "hex"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("\"0x%x = f(0x%x, 0x%x, %d, "));
+    }
+
+    #[test]
+    fn test_fail_condition() {
+        let input = b"int open(const char *path, int flags);";
+
+        let config = parse_config([
+            "ldpsc",
+            "-c",
+            "--fail",
+            "open=-1:ENOENT",
+            "--when",
+            "open:path==/etc/\"passwd\"",
+        ]).unwrap();
+        let mut output = Vec::new();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(transformed.contains("#include<string.h>\n"));
+        assert!(transformed.contains(
+            "    if (path && strcmp(path, \"/etc/\\\"passwd\\\"\") == 0) {\n        \
+             fprintf(output, \"%d = open(\\\"%s\\\", %d)\\n\", (int)(-1), (path ? path : \"(null)\"), flags);\n        \
+             errno = ENOENT;\n        \
+             return -1;\n    \
+             }\n    \
+             static int (*original_open)(const char *path, int flags);\n"
+        ));
+
+        let config = parse_config([
+            "ldpsc",
+            "-c",
+            "--fail",
+            "open=-1:EINVAL",
+            "--fail-rate",
+            "open=50",
+            "--when",
+            "open:flags==0",
+        ]).unwrap();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(transformed.contains("    if (flags == 0 && rand() % 100 < 50) {\n"));
+
+        let config = parse_config(["ldpsc", "-c", "--when", "open:flags==0"]).unwrap();
+        assert!(transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).is_err());
+
+        let config =
+            parse_config(["ldpsc", "-c", "--fail", "open=-1:2", "--when", "open:mode==0"]).unwrap();
+        assert!(transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).is_err());
+
+        assert!(parse_config(["ldpsc", "-c", "--when", "open:flags=0"]).is_err());
+        assert!(parse_config(["ldpsc", "-c", "--when", "open==0"]).is_err());
+    }
+
+    #[test]
+    fn test_qualified_return_types() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+
+        for (prototype, signature, pointer) in &[
+            (
+                &b"const int f(int a);"[..],
+                "const int f(int a) {\n",
+                "    static int (*original_f)(int a);\n",
             ),
-            many0!(
-                value!(
-                    (),
-                    tag!("*")
+            (
+                &b"const char *f(int a);"[..],
+                "const char *f(int a) {\n",
+                "    static const char *(*original_f)(int a);\n",
+            ),
+            (
+                &b"int * const f(int a);"[..],
+                "int *const f(int a) {\n",
+                "    static int *(*original_f)(int a);\n",
+            ),
+            (
+                &b"const char * const volatile f(int a);"[..],
+                "const char *const volatile f(int a) {\n",
+                "    static const char *(*original_f)(int a);\n",
+            ),
+            (
+                &b"const char * const *f(int a);"[..],
+                "const char *const *f(int a) {\n",
+                "    static const char *const *(*original_f)(int a);\n",
+            ),
+        ] {
+            let (_, function) = function(prototype).unwrap();
+            let mut definition = String::new();
+            function.get_definition(&mut definition, &config).unwrap();
+            assert!(definition.starts_with(signature));
+            assert!(definition.contains(pointer));
+        }
+    }
+
+    #[test]
+    fn test_export_symbols() {
+        let (_, function) = function(b"int close(int fd);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c", "--export-symbols"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.starts_with(
+            "__attribute__((visibility(\"default\")))\nint close(int fd) {\n"
+        ));
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.starts_with("int close(int fd) {\n"));
+    }
+
+    #[test]
+    fn test_per_thread_log() {
+        let config = parse_config(["ldpsc", "-c", "--per-thread-log", "logs"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"int close(int fd);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<sys/syscall.h>\n"));
+        assert!(transformed.contains("static __thread FILE *ldpsc_output;\n"));
+        assert!(transformed.contains("    mkdir(\"logs\", 0777);\n"));
+        assert!(transformed.contains(
+            "        snprintf(path, sizeof(path), \"logs/thread-%ld.log\", (long)syscall(SYS_gettid));\n"
+        ));
+        assert!(transformed.contains("    FILE *output = ldpsc_thread_output();\n"));
+        assert!(!transformed.contains("fclose(output);"));
+
+        assert!(parse_config(["ldpsc", "-c", "--per-thread-log", "logs", "-d", "log.txt"]).is_err());
+    }
+
+    #[test]
+    fn test_no_header_defines() {
+        let config = parse_config(["ldpsc", "-c", "--no-header-defines"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"int close(int fd);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.starts_with("#include<dlfcn.h>\n"));
+        assert!(!transformed.contains("_GNU_SOURCE"));
+    }
+
+    #[test]
+    fn test_binary_log_format() {
+        let input = b"ssize_t read(int fd, void *buf, size_t count);\nvoid free(void *ptr);";
+
+        let config = parse_config(["ldpsc", "-c", "--log-format", "binary", "-d", "calls.bin"]).unwrap();
+        let mut output = Vec::new();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(!transformed.contains("fprintf"));
+        assert!(transformed.contains("    memcpy(word, value, size < sizeof(*word) ? size : sizeof(*word));\n"));
+        assert!(transformed.contains(
+            "    int output = open(\"calls.bin\", O_WRONLY | O_CREAT | O_APPEND, 0644);\n"
+        ));
+        assert!(transformed.contains(
+            "    {\n        \
+             uint64_t ldpsc_log_record[7] = {0, 3, 4};\n        \
+             ldpsc_store_word(&ldpsc_log_record[3], &fd, sizeof(fd));\n        \
+             ldpsc_store_word(&ldpsc_log_record[4], &buf, sizeof(buf));\n        \
+             ldpsc_store_word(&ldpsc_log_record[5], &count, sizeof(count));\n        \
+             ssize_t ldpsc_logged_result = result;\n        \
+             ldpsc_store_word(&ldpsc_log_record[6], &ldpsc_logged_result, \
+             sizeof(ldpsc_logged_result));\n        \
+             (void)write(output, ldpsc_log_record, sizeof(ldpsc_log_record));\n    \
+             }\n    \
+             close(output);\n"
+        ));
+        assert!(transformed.contains("        uint64_t ldpsc_log_record[4] = {1, 1, 1};\n"));
+
+        let schema =
+            binary_log_schema(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert_eq!(
+            schema,
+            "0\tread\tssize_t\tint\tvoid *\tsize_t\n1\tfree\tvoid\tvoid *\n"
+        );
+    }
+
+    #[test]
+    fn test_function_pointer_return() {
+        let input = b"void (*signal(int sig, void (*handler)(int)))(int);\nint puts(const char *s);";
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        assert_eq!(
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)),
+            Err(ParseError {
+                message: "Function pointer return types are not supported, \
+                          found in the declaration of 'signal'"
+                    .to_string(),
+                location: Some((1, 1)),
+            })
+        );
+
+        let config = parse_config(["ldpsc", "-c", "--lenient"]).unwrap();
+        let list =
+            list_functions(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert_eq!(list, "puts\t1\tint\n");
+    }
+
+    #[test]
+    fn test_annotate() {
+        let input = b"// lib: c\n  int  open(const char *path, int flags /*hex*/);\n";
+
+        let config = parse_config(["ldpsc", "-c", "--annotate"]).unwrap();
+        let mut output = Vec::new();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(transformed.contains(
+            "\n/* from input: int  open(const char *path, int flags /*hex* /); */\n\
+             int open(const char *path, int flags) {\n"
+        ));
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(!transformed.contains("from input"));
+    }
+
+    #[test]
+    fn test_missing_original_check() {
+        let config = parse_config(["ldpsc", "-c", "-d", "log.txt"]).unwrap();
+
+        let (_, close) = function(b"int close(int fd);").unwrap();
+        let mut definition = String::new();
+        close.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "    }\n    \
+             if (!original_close) {\n        \
+             fprintf(output, \"ldpsc: could not find 'close': %s\\n\", dlerror());\n        \
+             fflush(output);\n        \
+             abort();\n    \
+             }\n"
+        ));
+
+        let (_, free) = function(b"void free(void *ptr);").unwrap();
+        let mut definition = String::new();
+        free.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "    if (!original_free) {\n        \
+             fprintf(output, \"ldpsc: could not find 'free': %s\\n\", dlerror());\n        \
+             fclose(output);\n        \
+             return;\n    \
+             }\n"
+        ));
+    }
+
+    #[test]
+    fn test_cached_original() {
+        let (_, function) = function(b"int close(int fd);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "    static int (*original_close)(int fd);\n    \
+             if (!__atomic_load_n(&original_close, __ATOMIC_ACQUIRE)) {\n        \
+             __atomic_store_n(&original_close, (int (*)(int fd))dlsym(RTLD_NEXT, \"close\"), __ATOMIC_RELEASE);\n    \
+             }\n"
+        ));
+        assert_eq!(definition.matches("dlsym(").count(), 1);
+    }
+
+    #[test]
+    fn test_storage_specifiers() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        let list = list_functions(
+            b"extern int foo(int x);\nextern  inline int bar(int x);\nstatic int baz(int x);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert_eq!(list, "foo\t1\tint\nbar\t1\tint\nbaz\t1\tint\n");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "ldpsc: warning: 'bar' is declared static or inline and cannot be interposed\n\
+             ldpsc: warning: 'baz' is declared static or inline and cannot be interposed\n"
+        );
+
+        assert!(function(b"externint foo(int x);").is_err());
+
+        let config = parse_config(["ldpsc", "-c", "--strict"]).unwrap();
+        let mut output = Vec::new();
+        assert!(list_functions(
+            b"static int bar(int x);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config)
+        ).is_err());
+    }
+
+    #[test]
+    fn test_template() {
+        let (_, function) = function(b"int close(int fd);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c", "--template", "{pid}:{fn}({args})"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(
+            definition.contains("    fprintf(output, \"%d:close(%d)\\n\", (int)getpid(), fd);\n")
+        );
+
+        let config =
+            parse_config(["ldpsc", "-c", "--template", "[{tid}] {fn} -> {ret} 100%"]).unwrap();
+        let mut definition = String::new();
+        function.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "    fprintf(output, \"[%ld] close -> %d 100%%\\n\", (long)syscall(SYS_gettid), result);\n"
+        ));
+
+        assert!(parse_config(["ldpsc", "-c", "--template", "{fn}({arguments})"]).is_err());
+        assert!(parse_config(["ldpsc", "-c", "--template", "{fn"]).is_err());
+    }
+
+    #[test]
+    fn test_struct_return() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"div_t div(int numerator, int denominator);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<stdlib.h>\n"));
+        assert!(transformed.contains(
+            "    div_t result = original_div(numerator, denominator);\n    \
+             fprintf(output, \"{struct} = div(%d, %d)\\n\", numerator, denominator);\n"
+        ));
+        assert!(transformed.contains("    return result;\n"));
+    }
+
+    #[test]
+    fn test_record() {
+        let config = parse_config(["ldpsc", "-c", "--record", "calls.rec"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"ssize_t read(int fd, void *buf /* length: count */, size_t count);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<sys/uio.h>\n"));
+        assert!(transformed.contains(
+            "    ssize_t result = original_read(fd, buf, count);\n    \
+             {\n        \
+             int ldpsc_record = open(\"calls.rec\", O_WRONLY | O_CREAT | O_APPEND, 0644);\n        \
+             uint64_t ldpsc_header[2] = {0, 5};\n        \
+             uint64_t ldpsc_sizes[5] = {sizeof(fd), sizeof(buf), sizeof(count), \
+             (buf ? (uint64_t)(count) : 0), sizeof(result)};\n        \
+             struct iovec ldpsc_fields[11] = {\n            \
+             {ldpsc_header, sizeof(ldpsc_header)},\n            \
+             {&ldpsc_sizes[0], sizeof(ldpsc_sizes[0])}, \
+             {(void *)&fd, ldpsc_sizes[0]},\n            \
+             {&ldpsc_sizes[1], sizeof(ldpsc_sizes[1])}, \
+             {(void *)&buf, ldpsc_sizes[1]},\n            \
+             {&ldpsc_sizes[2], sizeof(ldpsc_sizes[2])}, \
+             {(void *)&count, ldpsc_sizes[2]},\n            \
+             {&ldpsc_sizes[3], sizeof(ldpsc_sizes[3])}, \
+             {(void *)buf, ldpsc_sizes[3]},\n            \
+             {&ldpsc_sizes[4], sizeof(ldpsc_sizes[4])}, \
+             {(void *)&result, ldpsc_sizes[4]},\n        \
+             };\n        \
+             (void)writev(ldpsc_record, ldpsc_fields, 11);\n        \
+             close(ldpsc_record);\n    \
+             }\n"
+        ));
+
+        // Calls without any fields have no sizes.
+        let transformed = transform_file(
+            b"void sync(void);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains(
+            "        uint64_t ldpsc_header[2] = {0, 0};\n        \
+             struct iovec ldpsc_fields[1] = {\n"
+        ));
+
+        let mut diagnostics = Diagnostics::new(&mut output, &config);
+        assert!(transform_file(b"int f(int n /* length: n */);", &config, &mut diagnostics).is_err());
+        assert!(
+            transform_file(b"int f(void *p /* length: n */);", &config, &mut diagnostics).is_err()
+        );
+    }
+
+    #[test]
+    fn test_atomic_type_specifier() {
+        let (_, f) = function(b"void f(_Atomic(int) *p);").unwrap();
+        let mut signature = String::new();
+        f.get_signature(&mut signature, None).unwrap();
+        assert_eq!(signature, "void f(_Atomic int *p)");
+
+        let (_, g) = function(b"_Atomic ( unsigned long ) g(const _Atomic(char) c);").unwrap();
+        let mut signature = String::new();
+        g.get_signature(&mut signature, None).unwrap();
+        assert_eq!(signature, "_Atomic unsigned long g(const _Atomic char c)");
+    }
+
+    #[test]
+    fn test_logging_function_stub() {
+        let config = parse_config(["ldpsc", "-c", "-d", "calls.log"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"size_t fwrite(const void *ptr, size_t size, size_t nmemb, void *stream);\n\
+              int puts(const char *s);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<sys/syscall.h>\n"));
+        assert!(transformed.contains("static void ldpsc_raw_log(int fd, const char *format, ...) {"));
+
+        let start = transformed.find("size_t fwrite(").unwrap();
+        let end = transformed.find("int puts(").unwrap();
+        let fwrite = &transformed[start..end];
+        assert!(fwrite.contains(
+            "    int output = (int)syscall(SYS_openat, AT_FDCWD, \"calls.log\", \
+             O_WRONLY | O_CREAT | O_APPEND, 0644);\n"
+        ));
+        assert!(fwrite.contains("    ldpsc_raw_log(output, \"%zu = fwrite("));
+        assert!(fwrite.contains("    syscall(SYS_close, output);\n"));
+        assert!(!fwrite.contains("fprintf"));
+        assert!(!fwrite.contains("fopen"));
+        assert!(!fwrite.contains("fflush"));
+
+        // Other stubs still use the C library.
+        assert!(transformed[end..].contains("    fprintf(output, \"%d = puts("));
+    }
+
+    #[test]
+    fn test_recording_function_stub() {
+        let config = parse_config(["ldpsc", "-c", "--record", "calls.rec"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"ssize_t writev(int fd, const void *iov, int iovcnt);\n\
+              int close(int fd);\n\
+              int puts(const char *s);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<sys/syscall.h>\n"));
+
+        let start = transformed.find("ssize_t writev(").unwrap();
+        let end = transformed.find("int puts(").unwrap();
+        let raw = &transformed[start..end];
+        assert!(raw.contains(
+            "        int ldpsc_record = (int)syscall(SYS_openat, AT_FDCWD, \"calls.rec\", \
+             O_WRONLY | O_CREAT | O_APPEND, 0644);\n"
+        ));
+        assert!(raw.contains("(void)syscall(SYS_writev, ldpsc_record, ldpsc_fields, 9);\n"));
+        assert!(raw.contains("        syscall(SYS_close, ldpsc_record);\n"));
+        assert!(!raw.contains("writev(ldpsc_record"));
+        assert!(!raw.contains("close(ldpsc_record"));
+
+        // Other stubs still use the C library.
+        assert!(transformed[end..].contains("(void)writev(ldpsc_record, ldpsc_fields, 5);\n"));
+        assert!(transformed[end..].contains("        close(ldpsc_record);\n"));
+    }
+
+    #[test]
+    fn test_bool_alias() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"bool is_ready(bool flag);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<stdbool.h>\n"));
+        assert!(transformed.contains("bool is_ready(bool flag) {\n"));
+        assert!(transformed.contains("\"%d = is_ready(%d)\\n\", result, flag);"));
+
+        let config = parse_config(["ldpsc", "-c", "--bool-names"]).unwrap();
+        let transformed = transform_file(
+            b"bool is_ready(bool flag);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains(
+            "\"%s = is_ready(%s)\\n\", \
+             (result ? \"true\" : \"false\"), (flag ? \"true\" : \"false\"));"
+        ));
+    }
+
+    #[test]
+    fn test_hook_header() {
+        let config = parse_config(["ldpsc", "-c", "--hook-header", "/src/hooks.h"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"int foo(const char *s, int n);\nvoid bar();",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains(
+            "#include \"/src/hooks.h\"\n\
+             \n\
+             __attribute__((weak)) void ldpsc_hook_foo(const char *s, int n, int *result);\n\
+             __attribute__((weak)) void ldpsc_hook_bar(void);\n"
+        ));
+        assert!(transformed.contains(
+            "    int result = original_foo(s, n);\n    \
+             if (ldpsc_hook_foo) {\n        \
+             ldpsc_hook_foo(s, n, &result);\n    \
+             }\n"
+        ));
+        assert!(transformed.contains(
+            "    original_bar();\n    \
+             if (ldpsc_hook_bar) {\n        \
+             ldpsc_hook_bar();\n    \
+             }\n"
+        ));
+
+        let config = parse_config(["ldpsc", "-c", "--hook-header", "hooks.h"]).unwrap();
+        assert!(config.hook_header.unwrap().ends_with("/hooks.h"));
+    }
+
+    #[test]
+    fn test_max_calls() {
+        let (_, close) = function(b"int close(int fd);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c", "--max-calls", "100"]).unwrap();
+        let mut definition = String::new();
+        close.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "    static unsigned long ldpsc_calls;\n    \
+             int ldpsc_logged = __atomic_add_fetch(&ldpsc_calls, 1, __ATOMIC_RELAXED) <= 100;\n"
+        ));
+        assert!(definition.contains(
+            "    if (ldpsc_logged) {\n        \
+             fprintf(output, \"%d = close(%d)\\n\", result, fd);\n    \
+             }\n"
+        ));
+
+        assert!(parse_config(["ldpsc", "-c", "--max-calls", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_hexdump_buffers() {
+        let (_, write) =
+            function(b"ssize_t write(int fd, const void *buf /* length: count */, size_t count);")
+                .unwrap();
+
+        let config = parse_config(["ldpsc", "-c", "--hexdump-buffers", "--hexdump-cap", "16"])
+            .unwrap();
+        let mut definition = String::new();
+        write.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "    fprintf(output, \"%zd = write(%d, %p, %zu)\", result, fd, buf, count);\n    \
+             fprintf(output, \" buf=\");\n    \
+             for (size_t offset = 0; buf && offset < (size_t)(count) && offset < 16; \
+             offset++) {\n        \
+             fprintf(output, \"%02x\", ((const unsigned char *)buf)[offset]);\n    \
+             }\n    \
+             fprintf(output, \"\\n\");\n"
+        ));
+
+        let config = parse_config(["ldpsc", "-c", "--hexdump-buffers"]).unwrap();
+        assert_eq!(config.hexdump_buffers, Some(64));
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        write.get_definition(&mut definition, &config).unwrap();
+        assert!(!definition.contains("%02x"));
+    }
+
+    #[test]
+    fn test_null_on_missing() {
+        let (_, malloc) = function(b"void *malloc(size_t size);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c", "--null-on-missing"]).unwrap();
+        let mut definition = String::new();
+        malloc.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "    if (!original_malloc) {\n        \
+             fprintf(output, \"ldpsc: could not find 'malloc': %s\\n\", dlerror());\n        \
+             return (void *){0};\n    \
+             }\n"
+        ));
+
+        let (_, div) = function(b"div_t div(int numerator, int denominator);").unwrap();
+        let mut definition = String::new();
+        div.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("        return (div_t){0};\n"));
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        malloc.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("        abort();\n"));
+        assert!(!definition.contains("{0}"));
+    }
+
+    #[test]
+    fn test_qualified_void_return() {
+        let (_, memchr) = function(b"const void *memchr(const void *s, int c, size_t n);").unwrap();
+        assert!(!memchr.return_type.is_void());
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        memchr.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.starts_with("const void *memchr(const void *s, int c, size_t n) {\n"));
+        assert!(definition.contains(
+            "    static const void *(*original_memchr)(const void *s, int c, size_t n);\n"
+        ));
+        assert!(definition.contains("    const void *result = original_memchr(s, c, n);\n"));
+        assert!(definition.contains("\"%p = memchr(%p, %d, %zu)\\n\", result, s, c, n);"));
+
+        let (_, memchr) = function(b"void const *memchr(void const *s, int c, size_t n);").unwrap();
+        let mut signature = String::new();
+        memchr.get_signature(&mut signature, None).unwrap();
+        assert_eq!(signature, "const void *memchr(const void *s, int c, size_t n)");
+
+        let (_, f) = function(b"volatile void f(int x);").unwrap();
+        assert!(f.return_type.is_void());
+        let mut definition = String::new();
+        f.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.starts_with("volatile void f(int x) {\n"));
+        assert!(definition.contains("    static void (*original_f)(int x);\n"));
+        assert!(definition.contains("    original_f(x);\n"));
+    }
+
+    #[test]
+    fn test_canonical() {
+        let config = parse_config(["ldpsc", "-c", "--canonical", "--annotate"]).unwrap();
+        let mut output = Vec::new();
+        let mut diagnostics = Diagnostics::new(&mut output, &config);
+        let first = transform_file(
+            b"int puts(const char *s);\nvoid *malloc(size_t size);\nint close(int fd);",
+            &config,
+            &mut diagnostics,
+        ).unwrap();
+        let second = transform_file(
+            b"int close(int fd);\n\nvoid  *\tmalloc( size_t size ) ;\nint puts(const char*s);",
+            &config,
+            &mut diagnostics,
+        ).unwrap();
+        assert_eq!(first, second);
+        assert!(first.contains("#include<dlfcn.h>\n#include<stddef.h>\n#include<stdio.h>\n"));
+        assert!(first.find("int close(").unwrap() < first.find("void *malloc(").unwrap());
+        assert!(first.find("void *malloc(").unwrap() < first.find("int puts(").unwrap());
+        assert!(first.contains("/* from input: void *malloc(size_t size); */\n"));
+    }
+
+    #[test]
+    fn test_check_value() {
+        let check = |declaration: &[u8], value| {
+            let (_, parsed) = function(declaration).unwrap();
+            parsed.return_type.check_value(value)
+        };
+
+        assert_eq!(check(b"int f(int x);", "-1"), None);
+        assert_eq!(check(b"unsigned long f(int x);", "0xffUL"), None);
+        assert_eq!(check(b"int f(int x);", "'a'"), None);
+        assert!(check(b"int f(int x);", "abc").is_some());
+        assert!(check(b"int f(int x);", "1.5").is_some());
+        assert_eq!(check(b"double f(int x);", "-1.5e3"), None);
+        assert!(check(b"double f(int x);", "inf").is_some());
+        assert_eq!(check(b"void *f(int x);", "NULL"), None);
+        assert_eq!(check(b"char *f(int x);", "\"text\""), None);
+        assert!(check(b"void *f(int x);", "-1").is_some());
+        assert_eq!(check(b"bool f(int x);", "false"), None);
+        assert_eq!(check(b"div_t f(int x);", "{0}"), None);
+    }
+
+    #[test]
+    fn test_deref_strings() {
+        let (_, getline) =
+            function(b"ssize_t getline(char **lineptr, size_t *n, void *stream);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c", "--deref-strings"]).unwrap();
+        let mut definition = String::new();
+        getline.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "\"%zd = getline(\\\"%s\\\", %p, %p)\\n\", result, \
+             (lineptr && *lineptr ? *lineptr : \"(null)\"), n, stream);"
+        ));
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        getline.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "\"%zd = getline(%p, %p, %p)\\n\", result, lineptr, n, stream);"
+        ));
+    }
+
+    #[test]
+    fn test_abort() {
+        let config =
+            parse_config(["ldpsc", "-c", "-d", "calls.log", "--abort", "free,close"]).unwrap();
+        assert_eq!(config.abort_functions, ["free", "close"]);
+
+        let (_, free) = function(b"void free(void *ptr);").unwrap();
+        let mut definition = String::new();
+        free.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.ends_with(
+            "    fprintf(output, \"free(%p)\\n\", ptr);\n    \
+             fclose(output);\n    \
+             abort();\n\
+             }\n"
+        ));
+        assert!(!definition.contains("original_free"));
+
+        let (_, puts) = function(b"int puts(const char *s);").unwrap();
+        let mut definition = String::new();
+        puts.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("original_puts"));
+
+        let config = parse_config(["ldpsc", "-c", "--abort", "free", "--fail", "free=:EINVAL"])
+            .unwrap();
+        let mut output = Vec::new();
+        assert_eq!(
+            transform_file(
+                b"void free(void *ptr);",
+                &config,
+                &mut Diagnostics::new(&mut output, &config)
+            ),
+            Err("'free' cannot both fail and abort".into())
+        );
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let config =
+            parse_config(["ldpsc", "-c", "--backtrace", "--backtrace-depth", "8"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"int close(int fd);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<execinfo.h>\n"));
+        assert!(transformed.contains(
+            "    fprintf(output, \"%d = close(%d)\\n\", result, fd);\n    \
+             fflush(output);\n    \
+             {\n        \
+             void *frames[8];\n        \
+             backtrace_symbols_fd(frames, backtrace(frames, 8), fileno(output));\n    \
+             }\n"
+        ));
+
+        let config = parse_config(["ldpsc", "-c", "--backtrace", "--log-point", "both"]).unwrap();
+        let transformed = transform_file(
+            b"int close(int fd);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert_eq!(transformed.matches("backtrace(frames, 16)").count(), 1);
+
+        assert!(parse_config(["ldpsc", "-c", "--backtrace-depth", "8"]).is_err());
+        assert!(parse_config(["ldpsc", "-c", "--backtrace", "--backtrace-depth", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_typeof() {
+        let input = b"int puts(const char *s);\n__typeof__(puts) *get_puts(void *handle);";
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        assert_eq!(
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)),
+            Err(ParseError {
+                message: "'__typeof__' is not supported, replace it with the type it refers to"
+                    .to_string(),
+                location: Some((2, 1)),
+            })
+        );
+
+        let input = b"int max(typeof(1) a, int b);";
+        assert_eq!(
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)),
+            Err(ParseError {
+                message: "'typeof' is not supported, replace it with the type it refers to"
+                    .to_string(),
+                location: Some((1, 9)),
+            })
+        );
+
+        assert_eq!(typeof_keyword(b"int typeofs(int mytypeof);"), None);
+
+        let input = b"int puts(const char *s);\n__typeof__(puts) *get_puts(void *handle);";
+        let config = parse_config(["ldpsc", "-c", "--lenient"]).unwrap();
+        let list =
+            list_functions(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert_eq!(list, "puts\t1\tint\n");
+    }
+
+    #[test]
+    fn test_dlsym_handle() {
+        let input = b"int close(int fd);";
+        let config = parse_config(["ldpsc", "-c", "--dlsym-handle", "default"]).unwrap();
+        let mut output = Vec::new();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(transformed.contains(
+            "        void *ldpsc_original = dlsym(RTLD_DEFAULT, \"close\");\n        \
+             if (ldpsc_original == (void *)close) {\n            \
+             ldpsc_original = dlsym(RTLD_NEXT, \"close\");\n        \
+             }\n        \
+             __atomic_store_n(&original_close, (int (*)(int fd))ldpsc_original, \
+             __ATOMIC_RELEASE);\n"
+        ));
+
+        let config = parse_config(["ldpsc", "-c", "--dlsym-handle", "next"]).unwrap();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(transformed.contains(")dlsym(RTLD_NEXT, \"close\"), __ATOMIC_RELEASE);\n"));
+
+        assert!(parse_config(["ldpsc", "-c", "--dlsym-handle", "self"]).is_err());
+    }
+
+    #[test]
+    fn test_sequence() {
+        let config = parse_config(["ldpsc", "-c", "--sequence"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"int close(int fd);\nint dup(int fd);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert_eq!(transformed.matches("static unsigned long ldpsc_sequence;\n").count(), 1);
+        assert_eq!(
+            transformed
+                .matches(
+                    "    unsigned long ldpsc_sequence_number = \
+                     __atomic_add_fetch(&ldpsc_sequence, 1, __ATOMIC_RELAXED);\n"
                 )
-            )
-        )
-    ,
-    |(qualifiers, specifier, pointer)| {
-            Type {
-                qualifiers,
-                specifier: from_utf8(specifier).expect("nom bug").to_string(),
-                pointer: pointer.len()
-            }
+                .count(),
+            2
+        );
+        assert!(transformed
+            .contains(
+                "fprintf(output, \"#%lu %d = close(%d)\\n\", ldpsc_sequence_number, result, fd);"
+            ));
+        assert!(transformed
+            .contains(
+                "fprintf(output, \"#%lu %d = dup(%d)\\n\", ldpsc_sequence_number, result, fd);"
+            ));
+
+        let config =
+            parse_config(["ldpsc", "-c", "--sequence", "--log-format", "binary"]).unwrap();
+        let transformed = transform_file(
+            b"int close(int fd);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(!transformed.contains("sequence"));
+    }
+
+    #[test]
+    fn test_array_parameters() {
+        let (_, f) =
+            function(b"void f(int a[static 10], char s[const], int n, long m[ ]);").unwrap();
+        let mut signature = String::new();
+        f.get_signature(&mut signature, None).unwrap();
+        assert_eq!(signature, "void f(int a[static 10], char s[const], int n, long m[])");
+        assert_eq!(f.parameters[0].0.to_string(), "int *");
+        assert_eq!(f.parameters[1].0.to_string(), "char *const");
+
+        let (_, g) = function(b"int g(const char *argv[const static 2]);").unwrap();
+        let mut signature = String::new();
+        g.get_signature(&mut signature, None).unwrap();
+        assert_eq!(signature, "int g(const char *argv[static const 2])");
+
+        let mut cast = String::new();
+        g.get_signature(&mut cast, Some("")).unwrap();
+        assert_eq!(cast, "int (*)(const char *argv[static const 2])");
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        g.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("fprintf(output, \"%d = g(%p)\\n\", result, argv);"));
+    }
+
+    #[test]
+    fn test_cxx() {
+        let config = parse_config(["ldpsc", "-c", "--cxx"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"int close(int fd);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        let start = transformed.find("\nextern \"C\" {\n").unwrap();
+        assert!(transformed.rfind("#include").unwrap() < start);
+        assert!(start < transformed.find("int close(int fd) {").unwrap());
+        assert!(transformed.ends_with("\n}\n\n}\n"));
+        assert!(transformed.starts_with("#ifndef _GNU_SOURCE\n#define _GNU_SOURCE\n#endif\n"));
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let transformed = transform_file(
+            b"int close(int fd);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(!transformed.contains("extern \"C\""));
+
+        // C-only parts of types are rewritten or rejected.
+        let config = parse_config(["ldpsc", "-c", "--cxx"]).unwrap();
+        let transformed = transform_file(
+            b"char *strcpy(char *restrict dest, const char src[restrict static 1]);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains(
+            "char *strcpy(char *__restrict dest, const char *__restrict src) {\n"
+        ));
+        assert_eq!(
+            transform_file(
+                b"void f(_Atomic(int) *p);",
+                &config,
+                &mut Diagnostics::new(&mut output, &config)
+            ),
+            Err(ParseError {
+                message: "'f' cannot be written as C++: _Atomic is not supported in C++"
+                    .to_string(),
+                location: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_prototype_round_trip() {
+        let samples: &[&[u8]] = &[
+            b"int puts(const char *s);",
+            b"void  *  malloc ( size_t size ) ;",
+            b"char*strchr(const char*s,int c);",
+            b"unsigned long long int strtoull(const char *restrict nptr, char **restrict endptr, \
+              int base);",
+            b"const volatile void *const f(int *const *volatile p, _Bool b);",
+            b"_Atomic(int) g(_Atomic(long) *p, bool flag);",
+            b"void const *h(int a[static 10], char s[const], long m[]);",
+            b"int printf(const char *restrict format , ... );",
+            b"int getchar( void );",
+            b"int foo( );",
+            b"long double i(signed char c, unsigned short *s, float f, double d);",
+        ];
+
+        for sample in samples {
+            let (_, parsed) = function(sample).unwrap();
+            let prototype = parsed.to_prototype_string();
+            let (rest, reparsed) = function(prototype.as_bytes()).unwrap();
+
+            assert!(rest.is_empty(), "{:?} was not parsed completely", prototype);
+            assert_eq!(parsed, reparsed, "{:?} did not round-trip", prototype);
+            assert_eq!(reparsed.to_prototype_string(), prototype);
         }
-    )
-);
+    }
+
+    #[test]
+    fn test_variadic() {
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"int dprintf(int fd, const char *fmt, ...);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("#include<stdarg.h>\n"));
+        assert!(transformed.contains("int dprintf(int fd, const char *fmt, ...) {\n"));
+        assert!(transformed.contains(
+            "    static int (*original_dprintf)(int fd, const char *fmt, \
+             va_list ldpsc_arguments);\n"
+        ));
+        assert!(transformed.contains(
+            "(int (*)(int fd, const char *fmt, va_list ldpsc_arguments))\
+             dlsym(RTLD_NEXT, \"vdprintf\")"
+        ));
+        assert!(transformed.contains(
+            "    va_list ldpsc_arguments;\n    \
+             va_start(ldpsc_arguments, fmt);\n    \
+             int result = original_dprintf(fd, fmt, ldpsc_arguments);\n    \
+             va_end(ldpsc_arguments);\n"
+        ));
+        assert!(transformed.contains(
+            "    fprintf(output, \"%d = dprintf(%d, \\\"%s\\\")\\n\", result, fd, \
+             (fmt ? fmt : \"(null)\"));\n"
+        ));
+
+        assert_eq!(
+            transform_file(
+                b"int f(...);",
+                &config,
+                &mut Diagnostics::new(&mut output, &config)
+            ),
+            Err(ParseError {
+                message: "'f' takes only variable arguments, which cannot be forwarded"
+                    .to_string(),
+                location: None,
+            })
+        );
+        assert!(function(b"int g(int a ...);").is_err());
+
+        // The variable arguments cannot be forwarded without a known va_list variant.
+        let open = b"int open(const char *pathname, int flags, ...);";
+        assert_eq!(
+            transform_file(open, &config, &mut Diagnostics::new(&mut output, &config)),
+            Err(ParseError {
+                message: "'open' has no known va_list variant to forward its variable arguments \
+                          to, use --va-list-variant to name one"
+                    .to_string(),
+                location: None,
+            })
+        );
+
+        let config =
+            parse_config(["ldpsc", "-c", "--va-list-variant", "open=my_vopen"]).unwrap();
+        let transformed = transform_file(
+            open,
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("dlsym(RTLD_NEXT, \"my_vopen\")"));
+        assert!(transformed.contains(
+            "    int result = original_open(pathname, flags, ldpsc_arguments);\n"
+        ));
+
+        // The variant of the aliased function is used.
+        let config = parse_config(["ldpsc", "-c", "--alias", "log=printf"]).unwrap();
+        let transformed = transform_file(
+            b"int log(const char *format, ...);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("dlsym(RTLD_NEXT, \"vprintf\")"));
+    }
+
+    #[test]
+    fn test_no_result() {
+        let config = parse_config(["ldpsc", "-c", "--no-result", "read,malloc"]).unwrap();
+        assert_eq!(config.no_result_functions, ["read", "malloc"]);
+
+        let (_, read) = function(b"ssize_t read(int fd, void *buf, size_t count);").unwrap();
+        let mut definition = String::new();
+        read.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "    ssize_t result = original_read(fd, buf, count);\n    \
+             fprintf(output, \"read(%d, %p, %zu)\\n\", fd, buf, count);\n"
+        ));
+        assert!(definition.contains("    return result;\n"));
+
+        let (_, close) = function(b"int close(int fd);").unwrap();
+        let mut definition = String::new();
+        close.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("fprintf(output, \"%d = close(%d)\\n\", result, fd);"));
+
+        let config =
+            parse_config(["ldpsc", "-c", "--no-result", "read", "--fail", "read=-1:EIO"]).unwrap();
+        let mut definition = String::new();
+        read.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains("fprintf(output, \"read(%d, %p, %zu)\\n\", fd, buf, count);"));
+        assert!(definition.contains("    return -1;\n"));
+    }
+
+    #[test]
+    fn test_flush() {
+        let (_, close) = function(b"int close(int fd);").unwrap();
+
+        let config = parse_config(["ldpsc", "-c", "--flush", "--log-point", "both"]).unwrap();
+        let mut definition = String::new();
+        close.get_definition(&mut definition, &config).unwrap();
+        assert_eq!(definition.matches("fflush(output);").count(), 2);
+        assert!(definition.contains(
+            "    fprintf(output, \"%d = close(%d)\\n\", result, fd);\n    \
+             fflush(output);\n"
+        ));
+
+        let config =
+            parse_config(["ldpsc", "-c", "--flush", "-d", "calls.log", "--log-point", "entry"])
+                .unwrap();
+        let mut definition = String::new();
+        close.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.contains(
+            "    fprintf(output, \"close(%d)\\n\", fd);\n    \
+             fflush(output);\n    \
+             int result = original_close(fd);\n"
+        ));
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut definition = String::new();
+        close.get_definition(&mut definition, &config).unwrap();
+        assert!(!definition.contains("fflush"));
+    }
+
+    #[test]
+    fn test_gnu_restrict() {
+        let (_, f) = function(b"char *f(char * __restrict dest, const char * __restrict__ src);")
+            .unwrap();
+        assert_eq!(f.parameters[0].0, parse_type(b"char *restrict dest").unwrap().1);
+        assert_eq!(f.parameters[1].0, parse_type(b"const char *restrict src").unwrap().1);
+
+        let mut signature = String::new();
+        f.get_signature(&mut signature, None).unwrap();
+        assert_eq!(signature, "char *f(char *restrict dest, const char *restrict src)");
+
+        assert!(function(b"int g(int __restricted);").is_ok());
+    }
+
+    #[test]
+    fn test_no_parameters() {
+        let (_, flush) = function(b"void flush(void);").unwrap();
+        assert!(flush.parameters.is_empty());
+
+        let config = parse_config(["ldpsc", "-c", "--hook-header", "/src/hooks.h"]).unwrap();
+        let mut definition = String::new();
+        flush.get_definition(&mut definition, &config).unwrap();
+        assert!(definition.starts_with("void flush(void) {\n"));
+        assert!(definition.contains("    static void (*original_flush)(void);\n"));
+        assert!(definition.contains("(void (*)(void))dlsym(RTLD_NEXT, \"flush\")"));
+        assert!(definition.contains(
+            "    original_flush();\n    \
+             if (ldpsc_hook_flush) {\n        \
+             ldpsc_hook_flush();\n    \
+             }\n    \
+             fprintf(output, \"flush()\\n\");\n"
+        ));
+
+        let (_, f) = function(b"void *f(void *p);").unwrap();
+        assert_eq!(f.parameters.len(), 1);
+    }
+
+    #[test]
+    fn test_unprototyped() {
+        let (_, foo) = function(b"int foo();").unwrap();
+        assert!(foo.unprototyped);
+        assert!(foo.parameters.is_empty());
+        assert!(!function(b"int foo(void);").unwrap().1.unprototyped);
+        assert!(!function(b"int foo(int a);").unwrap().1.unprototyped);
+
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        let mut output = Vec::new();
+        let transformed = transform_file(
+            b"int foo();",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("int foo() {\n"));
+        assert!(transformed.contains("(int (*)())dlsym(RTLD_NEXT, \"foo\")"));
+        assert!(transformed.contains(
+            "    int result = original_foo();\n    \
+             fprintf(output, \"%d = foo()\\n\", result);\n"
+        ));
+        assert_eq!(
+            output,
+            &b"ldpsc: warning: 'foo' is declared without a prototype and is assumed to take no \
+               arguments\n"[..]
+        );
+
+        let config = parse_config(["ldpsc", "-c", "--strict"]).unwrap();
+        assert_eq!(
+            transform_file(b"int foo();", &config, &mut Diagnostics::new(&mut output, &config)),
+            Err("'foo' is declared without a prototype and is assumed to take no arguments".into())
+        );
+    }
+
+    #[test]
+    fn test_escaped_debug_output() {
+        let mut output = String::new();
+
+        let config = parse_config(["ldpsc", "-c", "-d", "a\"b\\c.log"]).unwrap();
+        write_open_output(&mut output, &config, true).unwrap();
+        assert_eq!(
+            output,
+            "    int output = (int)syscall(SYS_openat, AT_FDCWD, \"a\\\"b\\\\c.log\", \
+             O_WRONLY | O_CREAT | O_APPEND, 0644);\n"
+        );
+
+        output.clear();
+        write_open_output(&mut output, &config, false).unwrap();
+        assert_eq!(output, "    FILE *output = fopen(\"a\\\"b\\\\c.log\", \"a\");\n");
+
+        let config =
+            parse_config(["ldpsc", "-c", "-d", "a\"b\\c.log", "--log-format", "binary"]).unwrap();
+        output.clear();
+        write_open_output(&mut output, &config, false).unwrap();
+        assert_eq!(
+            output,
+            "    int output = open(\"a\\\"b\\\\c.log\", O_WRONLY | O_CREAT | O_APPEND, 0644);\n"
+        );
+    }
+
+    #[test]
+    fn test_generated_names_do_not_shadow_parameters() {
+        let input = b"int f(int record, int sizes, int logged, const char *fields, ...);";
+        let mut output = Vec::new();
+
+        let config = parse_config([
+            "ldpsc",
+            "-c",
+            "--record",
+            "calls.rec",
+            "--max-calls",
+            "10",
+            "--sequence",
+            "--va-list-variant",
+            "f=vf",
+        ]).unwrap();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(transformed.contains("{(void *)&record, ldpsc_sizes[0]},"));
+        assert!(transformed.contains("{(void *)&sizes, ldpsc_sizes[1]},"));
+        assert!(transformed.contains("va_start(ldpsc_arguments, fields);"));
+        assert!(transformed.contains("    if (ldpsc_logged) {\n"));
+        for name in &["record", "sizes", "logged", "fields", "header", "arguments", "sequence"] {
+            assert!(!transformed.contains(&format!(" {} =", name)));
+            assert!(!transformed.contains(&format!(" {}[", name)));
+        }
+
+        let config =
+            parse_config(["ldpsc", "-c", "--log-format", "binary", "--va-list-variant", "f=vf"])
+                .unwrap();
+        let transformed =
+            transform_file(input, &config, &mut Diagnostics::new(&mut output, &config)).unwrap();
+        assert!(
+            transformed.contains("ldpsc_store_word(&ldpsc_log_record[3], &record, sizeof(record));")
+        );
+    }
+}
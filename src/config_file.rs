@@ -0,0 +1,93 @@
+//! This module reads default options from a configuration file.
+//!
+//! Every key in the configuration file corresponds to the long name of a command line option.
+//! Flags are set with boolean values, options that can be used multiple times take arrays.
+
+use std::{ffi::OsString, fs::File, io::Read, path::Path};
+use toml::Value;
+
+/// The configuration file that is used if it exists and no other one is given.
+pub const DEFAULT_CONFIG_FILE: &str = "ldpsc.toml";
+
+/// Options that cannot be set in a configuration file.
+const FORBIDDEN_KEYS: &[&str] = &["config", "command"];
+
+/// Reads the given configuration file and converts it to command line arguments.
+///
+/// The arguments are grouped by the name of the option they set.
+pub fn read_config_file(path: &Path) -> Result<Vec<(String, Vec<OsString>)>, String> {
+    let mut content = String::new();
+
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    to_args(&content).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Converts the content of a configuration file to command line arguments.
+fn to_args(content: &str) -> Result<Vec<(String, Vec<OsString>)>, String> {
+    let table = match content.parse::<Value>().map_err(|err| err.to_string())? {
+        Value::Table(table) => table,
+        _ => return Err("Expected a table".to_string()),
+    };
+
+    let mut args = Vec::new();
+
+    for (key, value) in &table {
+        if FORBIDDEN_KEYS.contains(&&key[..]) {
+            return Err(format!("The option {:?} cannot be set in a configuration file", key));
+        }
+
+        let option_args = match value {
+            Value::Boolean(true) => vec![format!("--{}", key).into()],
+            Value::Boolean(false) => continue,
+            Value::Array(values) => values
+                .iter()
+                .map(|value| to_arg(key, value))
+                .collect::<Result<_, _>>()?,
+            value => vec![to_arg(key, value)?],
+        };
+
+        args.push((key.clone(), option_args));
+    }
+
+    Ok(args)
+}
+
+/// Converts a single value of an option to a command line argument.
+fn to_arg(key: &str, value: &Value) -> Result<OsString, String> {
+    match value {
+        Value::String(value) => Ok(format!("--{}={}", key, value).into()),
+        Value::Integer(value) => Ok(format!("--{}={}", key, value).into()),
+        Value::Float(value) => Ok(format!("--{}={}", key, value).into()),
+        _ => Err(format!("Unsupported value for the option {:?}: {}", key, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_args() {
+        assert_eq!(
+            to_args("c-compiler = \"clang\"\ncapture = true\ncc-flag = [\"-O2\", \"-g\"]\ntimeout = 3\nstdout-file = \"out\"\nquiet = false"),
+            Ok(vec![
+                ("c-compiler".to_string(), vec![OsString::from("--c-compiler=clang")]),
+                ("capture".to_string(), vec![OsString::from("--capture")]),
+                (
+                    "cc-flag".to_string(),
+                    vec![OsString::from("--cc-flag=-O2"), OsString::from("--cc-flag=-g")]
+                ),
+                ("stdout-file".to_string(), vec![OsString::from("--stdout-file=out")]),
+                ("timeout".to_string(), vec![OsString::from("--timeout=3")]),
+            ])
+        );
+        assert_eq!(to_args(""), Ok(vec![]));
+        assert!(to_args("command = \"ls\"").is_err());
+        assert!(to_args("config = \"other.toml\"").is_err());
+        assert!(to_args("input = { file = \"a\" }").is_err());
+        assert!(to_args("input = ").is_err());
+    }
+}
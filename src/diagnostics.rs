@@ -0,0 +1,97 @@
+//! This module reports diagnostic messages to the user.
+
+use super::Config;
+use std::{fmt::Display, io::Write};
+
+/// Writes the diagnostic messages of ldpsc.
+pub struct Diagnostics<'a> {
+    /// The writer the messages are written to.
+    output: &'a mut dyn Write,
+    /// Whether messages about the progress are written.
+    verbose: bool,
+    /// Whether only errors are reported.
+    quiet: bool,
+}
+
+impl<'a> Diagnostics<'a> {
+    /// Creates new diagnostics for the given configuration writing to the given output.
+    pub fn new(output: &'a mut dyn Write, config: &Config) -> Diagnostics<'a> {
+        Diagnostics {
+            output,
+            verbose: config.verbose,
+            quiet: config.quiet,
+        }
+    }
+
+    /// Reports the progress of ldpsc, if verbose output is enabled.
+    pub fn verbose<T: Display>(&mut self, message: T) {
+        if self.verbose {
+            self.write(message);
+        }
+    }
+
+    /// Reports the result of ldpsc, unless quiet.
+    pub fn info<T: Display>(&mut self, message: T) {
+        if !self.quiet {
+            self.write(message);
+        }
+    }
+
+    /// Reports a problem that does not prevent ldpsc from continuing, unless quiet.
+    pub fn warning<T: Display>(&mut self, message: T) {
+        if !self.quiet {
+            self.write(format_args!("warning: {}", message));
+        }
+    }
+
+    /// Writes the given message.
+    fn write<T: Display>(&mut self, message: T) {
+        // Not being able to report a diagnostic is no reason to abort.
+        let _ = writeln!(self.output, "ldpsc: {}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_config;
+
+    #[test]
+    fn test_verbose() {
+        let mut output = Vec::new();
+        let config = parse_config(["ldpsc", "-c", "--verbose"]).unwrap();
+        Diagnostics::new(&mut output, &config).verbose("message");
+        assert_eq!(output, b"ldpsc: message\n");
+
+        let mut output = Vec::new();
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        Diagnostics::new(&mut output, &config).verbose("message");
+        assert_eq!(output, b"");
+    }
+
+    #[test]
+    fn test_info() {
+        let mut output = Vec::new();
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        Diagnostics::new(&mut output, &config).info("message");
+        assert_eq!(output, b"ldpsc: message\n");
+
+        let mut output = Vec::new();
+        let config = parse_config(["ldpsc", "-c", "--quiet"]).unwrap();
+        Diagnostics::new(&mut output, &config).info("message");
+        assert_eq!(output, b"");
+    }
+
+    #[test]
+    fn test_warning() {
+        let mut output = Vec::new();
+        let config = parse_config(["ldpsc", "-c"]).unwrap();
+        Diagnostics::new(&mut output, &config).warning("message");
+        assert_eq!(output, b"ldpsc: warning: message\n");
+
+        let mut output = Vec::new();
+        let config = parse_config(["ldpsc", "-c", "--quiet"]).unwrap();
+        Diagnostics::new(&mut output, &config).warning("message");
+        assert_eq!(output, b"");
+    }
+}
@@ -5,188 +5,1158 @@ extern crate clap;
 #[macro_use]
 extern crate nom;
 extern crate tempfile;
+extern crate toml;
 
 mod c_parser;
+mod config_file;
+mod diagnostics;
+mod makefile;
+mod script;
 
-use clap::{App, Arg};
+use c_parser::{
+    Condition, DlsymHandle, Failure, IntRadix, LogFormat, LogPoint, PointerFormat, TemplatePart,
+};
+use clap::{App, AppSettings, Arg};
+use diagnostics::Diagnostics;
 use std::{
-    fs::File,
-    io::{self, stdin, stdout, Read, Write},
-    path::Path,
-    process::Command,
+    collections::{hash_map::DefaultHasher, HashMap},
+    env,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    hash::{Hash, Hasher},
+    io::{self, stderr, stdin, stdout, BufRead, BufReader, Read, Write},
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+    process::{self, Child, Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
 };
-use tempfile::Builder;
+use tempfile::{Builder, TempDir};
 
 /// The main function for this application.
-fn main() -> Result<(), String> {
+fn main() {
     // Get the configuration.
     let config = get_config();
 
+    if let Err(err) = run(&config) {
+        match config.error_format {
+            ErrorFormat::Text => eprintln!("Error: {}", err.message),
+            ErrorFormat::Json => eprintln!("{}", err.to_json()),
+        }
+        process::exit(err.exit_code);
+    }
+}
+
+/// The format errors are reported in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    /// A readable message.
+    Text,
+    /// A JSON object.
+    Json,
+}
+
+/// The stage of the application an error happened in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    /// Reading the input and writing the output.
+    Setup,
+    /// Parsing the input and generating the code.
+    Parse,
+    /// Compiling the generated code.
+    Compile,
+    /// Running the command.
+    Run,
+}
+
+impl Stage {
+    /// Returns the name of the stage in machine readable errors.
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Setup => "setup",
+            Stage::Parse => "parse",
+            Stage::Compile => "compile",
+            Stage::Run => "run",
+        }
+    }
+}
+
+/// An error that stops the application.
+#[derive(Debug)]
+struct Error {
+    /// The message describing the error.
+    message: String,
+    /// The exit code the application exits with.
+    exit_code: i32,
+    /// The stage the error happened in.
+    stage: Stage,
+    /// The line and column in the input the error was found at, if it is known.
+    location: Option<(usize, usize)>,
+}
+
+impl Error {
+    /// Returns this error as happening in the given stage.
+    fn in_stage(self, stage: Stage) -> Error {
+        Error { stage, ..self }
+    }
+
+    /// Returns this error as a JSON object.
+    fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"stage\":\"{}\",\"message\":{}",
+            self.stage.name(),
+            json_string(&self.message)
+        );
+
+        if let Some((line, column)) = self.location {
+            json.push_str(&format!(",\"line\":{},\"column\":{}", line, column));
+        }
+
+        json.push('}');
+        json
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error {
+            message,
+            exit_code: 1,
+            stage: Stage::Setup,
+            location: None,
+        }
+    }
+}
+
+impl From<c_parser::ParseError> for Error {
+    fn from(err: c_parser::ParseError) -> Error {
+        Error {
+            location: err.location,
+            ..Error::from(err.message).in_stage(Stage::Parse)
+        }
+    }
+}
+
+/// Returns the given text as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+/// Runs the application with the given configuration.
+fn run(config: &Config) -> Result<(), Error> {
+    let mut stderr = stderr();
+    run_with_diagnostics(config, &mut Diagnostics::new(&mut stderr, config))
+}
+
+/// Runs the application with the given configuration, reporting diagnostics to the given ones.
+fn run_with_diagnostics(config: &Config, diagnostics: &mut Diagnostics) -> Result<(), Error> {
+    if config.list_supported_types {
+        write_file(
+            &config.output_file,
+            c_parser::list_supported_types(config).as_bytes(),
+        ).map_err(|err| format!("{}: {}", config.output_file, err))?;
+        return Ok(());
+    }
+
+    if config.self_test {
+        self_test(config, diagnostics)
+            .map_err(|err| Error::from(err).in_stage(Stage::Compile))?;
+        println!("self test passed");
+        return Ok(());
+    }
+
     // Read and transform the file.
-    let file_content =
-        read_file(&config.input_file).map_err(|err| format!("{}: {}", config.input_file, err))?;
-    let transformed_content = c_parser::transform_file(&file_content, &config)?;
+    let mut file_content = read_file(&config.input_file)?;
+
+    if config.preprocess {
+        file_content = preprocess(config, file_content, diagnostics)?;
+    }
+
+    let selected_config;
+    let config = if config.interactive {
+        selected_config = Config {
+            selected_functions: select_functions(config, &file_content)?,
+            ..config.clone()
+        };
+        &selected_config
+    } else {
+        config
+    };
+
+    if let Some(schema_path) = &config.schema {
+        // Diagnostics are reported when the file is transformed.
+        let mut sink = io::sink();
+        let schema = c_parser::binary_log_schema(
+            &file_content,
+            config,
+            &mut Diagnostics::new(&mut sink, config),
+        )?;
+        write_file(schema_path, schema.as_bytes())
+            .map_err(|err| format!("{}: {}", schema_path, err))?;
+    }
+
+    if let Some(symbol_list_path) = &config.symbol_list {
+        // Diagnostics are reported when the file is transformed.
+        let mut sink = io::sink();
+        let mut symbol_list = String::new();
+        for name in c_parser::function_names(
+            &file_content,
+            config,
+            &mut Diagnostics::new(&mut sink, config),
+        )? {
+            symbol_list.push_str(&name);
+            symbol_list.push('\n');
+        }
+        write_file(symbol_list_path, symbol_list.as_bytes())
+            .map_err(|err| format!("{}: {}", symbol_list_path, err))?;
+    }
+
+    // Only list the functions if requested.
+    if config.list {
+        let list = c_parser::list_functions(&file_content, config, diagnostics)?;
+        write_file(&config.output_file, list.as_bytes())
+            .map_err(|err| format!("{}: {}", config.output_file, err))?;
+        return Ok(());
+    }
+
+    // Write both the C code and the shared object to the output directory if requested.
+    if let Some(dir) = &config.output_dir {
+        let name = artifact_name(config);
+        let files = if config.split_by_function {
+            c_parser::transform_file_split(&file_content, config, diagnostics)?
+                .into_iter()
+                .map(|(function, code)| (format!("{}-{}", name, function), code))
+                .collect()
+        } else {
+            let code = c_parser::transform_file(&file_content, config, diagnostics)?;
+            vec![(name, code)]
+        };
+
+        write_output_dir(config, Path::new(dir), &files, diagnostics)
+            .map_err(|err| Error::from(err).in_stage(Stage::Compile))?;
+        return Ok(());
+    }
+
+    let mut transformed_content = Vec::new();
+    let stubs =
+        c_parser::transform_file_to(&file_content, config, diagnostics, &mut transformed_content)?;
+    let transformed_content =
+        String::from_utf8(transformed_content).expect("generated code is valid UTF-8");
+
+    if let Some(script_path) = &config.script {
+        let script = script::generate(config, &transformed_content)?;
+        write_file(script_path, script.as_bytes())
+            .map_err(|err| format!("{}: {}", script_path, err))?;
+    }
 
     // Output the C code if necessary.
     if config.output_to_c {
+        if config.makefile.is_some() && config.output_file == "-" {
+            let message = "A Makefile can only be emitted when the C code is written to a file";
+            Err(message.to_string())?;
+        }
+
+        // The Makefile is generated first, so nothing is written if it cannot be.
+        let makefile = match &config.makefile {
+            Some(makefile_path) => Some((
+                makefile_path,
+                makefile::generate(
+                    config,
+                    Path::new(&config.output_file),
+                    Path::new(makefile_path),
+                )?,
+            )),
+            None => None,
+        };
+
         write_file(&config.output_file, transformed_content.as_bytes())
             .map_err(|err| format!("{}: {}", config.output_file, err))?;
+
+        if let Some((makefile_path, makefile)) = makefile {
+            write_file(makefile_path, makefile.as_bytes())
+                .map_err(|err| format!("{}: {}", makefile_path, err))?;
+        }
+
+        diagnostics.info(format_args!("generated {} stub(s)", stubs));
+        return Ok(());
+    }
+
+    // Compile the shared object in a temporary directory, unless it is cached.
+    let (_tmp_dir, so_path) = compile_cached(config, &transformed_content, diagnostics)
+        .map_err(|err| Error::from(err).in_stage(Stage::Compile))?;
+
+    // Copy the shared object if necessary.
+    if config.create_shared_object {
+        let shared_object =
+            fs::read(&so_path).map_err(|err| format!("{}: {}", so_path.display(), err))?;
+        write_file(&config.output_file, &shared_object)
+            .map_err(|err| format!("{}: {}", config.output_file, err))?;
+        diagnostics.info(format_args!("generated {} stub(s)", stubs));
         return Ok(());
     }
 
+    // Run the command.
+    diagnostics.verbose(format_args!("running {:?}", config.command.as_ref().unwrap_or(&vec![])));
+    run_command(config, &so_path).map_err(|err| err.in_stage(Stage::Run))
+}
+
+/// Compiles the given C code to a shared object in a new temporary directory.
+///
+/// The shared object is removed together with the returned directory.
+fn compile_in_temp_dir(
+    config: &Config,
+    code: &str,
+    diagnostics: &mut Diagnostics,
+) -> Result<(TempDir, PathBuf), String> {
     // Create a temporary directory.
-    let tmp_dir = Builder::new()
-        .prefix("ldpsc")
-        .tempdir()
-        .map_err(|err| format!("Error creating temp directory: {}", err))?;
+    let tmp_dir = create_temp_dir(config)?;
+    diagnostics.verbose(format_args!(
+        "using temporary directory {}",
+        tmp_dir.path().display()
+    ));
 
     // Write the C file in the temporary directory.
     let mut output_path = tmp_dir.path().to_path_buf();
     output_path.push("output.c");
-    write_file(
-        output_path.to_str().unwrap(),
-        transformed_content.as_bytes(),
-    ).map_err(|err| format!("{:?}: {}", output_path, err))?;
+    fs::write(&output_path, code).map_err(|err| format!("{}: {}", output_path.display(), err))?;
 
     // Run the C compiler.
-    let so_path = run_cc(&config, tmp_dir.path(), &output_path)?;
+    let so_path = tmp_dir.path().join("output.so");
+    run_cc(config, &output_path, &so_path, diagnostics)?;
 
-    // Copy the shared object if necessary.
-    if config.create_shared_object {
-        write_file(
-            &config.output_file,
-            &read_file(&so_path).map_err(|err| format!("{}: {}", &so_path, err))?,
-        ).map_err(|err| format!("{}: {}", config.output_file, err))?;
-        return Ok(());
+    Ok((tmp_dir, so_path))
+}
+
+/// Compiles the given C code to a shared object, unless it is found in the cache directory.
+///
+/// Newly compiled shared objects are stored in the cache directory. The temporary directory they
+/// were compiled in is returned with them.
+fn compile_cached(
+    config: &Config,
+    code: &str,
+    diagnostics: &mut Diagnostics,
+) -> Result<(Option<TempDir>, PathBuf), String> {
+    let cache_dir = match &config.cache_dir {
+        Some(cache_dir) => cache_dir,
+        None => {
+            let (tmp_dir, so_path) = compile_in_temp_dir(config, code, diagnostics)?;
+            return Ok((Some(tmp_dir), so_path));
+        }
+    };
+
+    let key = cache_key(config, code);
+    let cached_path = cache_dir.join(format!("{:016x}.so", key));
+    if cached_path.is_file() {
+        diagnostics.verbose(format_args!("using cached shared object {}", cached_path.display()));
+        return Ok((None, cached_path));
     }
 
-    // Run the command.
-    run_command(&config, &so_path)
+    let (tmp_dir, so_path) = compile_in_temp_dir(config, code, diagnostics)?;
+
+    // Concurrent runs may store the same shared object, so it is moved into place as a whole.
+    let partial_path = cache_dir.join(format!("{:016x}.so.{}", key, process::id()));
+    fs::create_dir_all(cache_dir)
+        .and_then(|_| fs::copy(&so_path, &partial_path))
+        .and_then(|_| fs::rename(&partial_path, &cached_path))
+        .map_err(|err| format!("{}: {}", cached_path.display(), err))?;
+    diagnostics.verbose(format_args!("cached shared object {}", cached_path.display()));
+
+    Ok((Some(tmp_dir), so_path))
 }
 
-/// Runs the C compiler on the given file.
-fn run_cc(config: &Config, tmp_dir: &Path, output_path: &Path) -> Result<String, String> {
-    let mut command = Command::new(&config.c_compiler);
+/// Returns the key of the shared object compiled from the given code in the cache.
+///
+/// The key depends on the code and the compiler command, but not on the compiler version.
+fn cache_key(config: &Config, code: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    code.hash(&mut hasher);
+
+    let command = build_cc_command(config, Path::new("output.c"), Path::new("output.so"));
+    command.get_program().hash(&mut hasher);
+    for arg in command.get_args() {
+        arg.hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+/// The input used by the self test.
+const SELF_TEST_INPUT: &[u8] = b"int puts(const char *s);";
+
+/// Checks that a stub for a known function can be generated and compiled.
+fn self_test(config: &Config, diagnostics: &mut Diagnostics) -> Result<(), String> {
+    let code = c_parser::transform_file(SELF_TEST_INPUT, config, diagnostics)
+        .map_err(|err| format!("Self test failed: {}", err))?;
+    compile_in_temp_dir(config, &code, diagnostics)
+        .map_err(|err| format!("Self test failed: {}", err))?;
+
+    Ok(())
+}
+
+/// Asks on the controlling terminal which of the functions in the content should be stubbed.
+///
+/// Without a controlling terminal, all functions are stubbed.
+fn select_functions(config: &Config, content: &[u8]) -> Result<Option<Vec<String>>, Error> {
+    let mut terminal = match OpenOptions::new().read(true).write(true).open("/dev/tty") {
+        Ok(terminal) => terminal,
+        Err(_) => return Ok(None),
+    };
+
+    // Diagnostics are reported when the file is transformed.
+    let mut sink = io::sink();
+    let names =
+        c_parser::function_names(content, config, &mut Diagnostics::new(&mut sink, config))?;
+
+    let input = terminal
+        .try_clone()
+        .map_err(|err| format!("/dev/tty: {}", err))?;
+    let selected = prompt_selection(&names, BufReader::new(input), &mut terminal)
+        .map_err(|err| format!("/dev/tty: {}", err))?;
 
-    let mut so_path = tmp_dir.to_path_buf();
-    so_path.push("output.so");
+    Ok(Some(selected))
+}
+
+/// Asks whether each of the given functions should be stubbed, returning the selected ones.
+///
+/// Answers starting with `n` exclude a function, all others include it.
+fn prompt_selection<R: BufRead, W: Write>(
+    names: &[String],
+    mut input: R,
+    mut output: W,
+) -> io::Result<Vec<String>> {
+    let mut selected = Vec::new();
+
+    for name in names {
+        write!(output, "stub '{}'? [Y/n] ", name)?;
+        output.flush()?;
+
+        let mut answer = String::new();
+        input.read_line(&mut answer)?;
+
+        if !answer.trim_start().starts_with(['n', 'N']) {
+            selected.push(name.clone());
+        }
+    }
+
+    Ok(selected)
+}
+
+/// Runs the C preprocessor on the given content.
+fn preprocess(
+    config: &Config,
+    content: Vec<u8>,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<u8>, String> {
+    let mut command = Command::new(&config.c_compiler);
 
+    // Line markers are left out, because they cannot be parsed.
     command
-        .arg(output_path)
-        .arg("-o")
-        .arg(&so_path)
-        .arg("-shared")
-        .arg("-fPIC")
-        .arg("-ldl");
+        .arg("-E")
+        .arg("-P")
+        .args(&config.cc_flags)
+        .args(["-x", "c", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped());
+
+    diagnostics.verbose(format_args!("running {:?}", command));
+
+    let mut child = command
+        .spawn()
+        .map_err(|err| format!("Running {:?} failed: {}", command, err))?;
 
-    let output = command
-        .output()
+    // The input is written on a separate thread, so the preprocessor cannot block on its output.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = thread::spawn(move || stdin.write_all(&content));
+
+    let output = child
+        .wait_with_output()
         .map_err(|err| format!("Running {:?} failed: {}", command, err))?;
+    writer
+        .join()
+        .expect("writing thread panicked")
+        .map_err(|err| format!("Writing to {:?} failed: {}", command, err))?;
 
     if !output.status.success() {
         Err(format!("{:?} failed", command))?;
     }
 
-    Ok(so_path
-        .to_str()
-        .expect("Path could not be converted to string.")
-        .to_string())
+    Ok(output.stdout)
 }
 
-/// Runs the given command preloading the given library.
-fn run_command(config: &Config, preload_path: &str) -> Result<(), String> {
-    if let Some(args) = &config.command {
-        if args.len() == 0 {
-            return Err("No command to run found.".to_string());
-        }
+/// Writes the given C code and the shared objects compiled from it to the given directory.
+///
+/// The files are given as their name without extension and their C code.
+fn write_output_dir(
+    config: &Config,
+    dir: &Path,
+    files: &[(String, String)],
+    diagnostics: &mut Diagnostics,
+) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+
+    for (name, code) in files {
+        let c_path = dir.join(format!("{}.c", name));
+        let so_path = dir.join(format!("{}.so", name));
 
-        let mut command = Command::new(&args[0]);
+        File::create(&c_path)
+            .and_then(|mut file| file.write_all(code.as_bytes()))
+            .map_err(|err| format!("{}: {}", c_path.display(), err))?;
+        run_cc(config, &c_path, &so_path, diagnostics)?;
+    }
+
+    Ok(())
+}
 
-        for arg in args.iter().skip(1) {
-            command.arg(&arg);
+/// Returns the name of the files written to the output directory.
+///
+/// It is either given explicitly or derived from the input file. Without either, the process id
+/// makes the name unique, so concurrent runs sharing an output directory do not collide.
+fn artifact_name(config: &Config) -> String {
+    let name = if let Some(name) = &config.name {
+        name.clone()
+    } else {
+        match Path::new(&config.input_file).file_stem() {
+            Some(stem) if config.input_file != "-" => stem.to_string_lossy().into_owned(),
+            _ => format!("output-{}", process::id()),
         }
+    };
+
+    sanitize_name(&name)
+}
+
+/// Replaces the characters of a name that are unsafe in file names and Makefiles with `_`.
+fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    // A leading dot would hide the files and `..` would not be a file at all.
+    match name.strip_prefix('.') {
+        Some(rest) => format!("_{}", rest),
+        None => name,
+    }
+}
+
+/// Creates the temporary directory to compile the shared object in.
+fn create_temp_dir(config: &Config) -> Result<TempDir, String> {
+    let mut builder = Builder::new();
+    builder.prefix("ldpsc");
+
+    if let Some(parent) = &config.temp_dir {
+        builder.tempdir_in(parent)
+    } else {
+        builder.tempdir()
+    }.map_err(|err| format!("Error creating temp directory: {}", err))
+}
+
+/// Runs the C compiler on the given file, creating the given shared object.
+fn run_cc(
+    config: &Config,
+    output_path: &Path,
+    so_path: &Path,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), String> {
+    let mut command = build_cc_command(config, output_path, so_path);
 
-        command.env("LD_PRELOAD", preload_path);
+    diagnostics.verbose(format_args!("running {:?}", command));
 
-        let status = command
-            .status()
+    let status = if let Some(timeout) = config.compiler_timeout {
+        let mut child = command
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
             .map_err(|err| format!("Running {:?} failed: {}", command, err))?;
 
-        if !status.success() {
-            if let Some(exit_code) = status.code() {
-                Err(format!(
+        let status = wait_timeout(&mut child, timeout)
+            .map_err(|err| format!("Waiting for {:?} failed: {}", command, err))?;
+
+        if let Some(status) = status {
+            status
+        } else {
+            child
+                .kill()
+                .and_then(|_| child.wait())
+                .map_err(|err| format!("Killing {:?} failed: {}", command, err))?;
+
+            return Err(format!(
+                "The compiler timed out after {} seconds",
+                timeout.as_secs()
+            ));
+        }
+    } else {
+        command
+            .output()
+            .map_err(|err| format!("Running {:?} failed: {}", command, err))?
+            .status
+    };
+
+    if !status.success() {
+        Err(format!("{:?} failed", command))?;
+    }
+
+    diagnostics.verbose(format_args!("created {}", so_path.display()));
+
+    Ok(())
+}
+
+/// The C compiler flags that are needed to build the shared object.
+const REQUIRED_CC_FLAGS: &[&str] = &["-shared", "-fPIC", "-ldl"];
+
+/// The flags added to the C compiler flags by `--hardened`.
+const HARDENING_FLAGS: &[&str] = &["-z", "now", "-z", "relro", "-fstack-protector-strong"];
+
+/// Builds the command compiling the given C file to the given shared object.
+fn build_cc_command(config: &Config, output_path: &Path, so_path: &Path) -> Command {
+    let mut command = Command::new(&config.c_compiler);
+
+    command
+        .args(cc_language_flags(config))
+        .arg(output_path)
+        .arg("-o")
+        .arg(so_path)
+        .args(REQUIRED_CC_FLAGS)
+        .args(extra_cc_flags(config));
+
+    command
+}
+
+/// Returns the C compiler flags selecting the language of the generated code.
+///
+/// They apply to the files following them, so they come before the generated code.
+fn cc_language_flags(config: &Config) -> &'static [&'static str] {
+    if config.cxx {
+        &["-x", "c++"]
+    } else {
+        &[]
+    }
+}
+
+/// Returns the C compiler flags that are added to the required ones.
+fn extra_cc_flags(config: &Config) -> Vec<String> {
+    let mut flags = vec![format!("-std={}", config.c_standard)];
+
+    if let Some(bits) = config.bits {
+        flags.push(format!("-m{}", bits));
+    }
+
+    if config.backtrace.is_some() {
+        flags.push("-rdynamic".to_string());
+    }
+
+    if config.no_header_defines {
+        flags.push("-D_GNU_SOURCE".to_string());
+    }
+
+    if config.hardened {
+        flags.extend(HARDENING_FLAGS.iter().map(|flag| flag.to_string()));
+    }
+
+    flags.extend(config.cc_flags.iter().cloned());
+
+    flags
+}
+
+/// Runs the given command preloading the given library.
+///
+/// If the command finishes unsuccessfully, the error has the exit code of the command.
+fn run_command<P: AsRef<Path>>(config: &Config, preload_path: P) -> Result<(), Error> {
+    let mut command = build_command(config, preload_path, env::vars_os())?;
+    let (status, captured) = execute_command(config, &mut command)?;
+
+    if let Some(captured) = captured {
+        write_captured_output(config, &captured)?;
+    }
+
+    if !status.success() {
+        if let Some(exit_code) = status.code() {
+            Err(Error {
+                exit_code,
+                ..Error::from(format!(
                     "{:?} finished unsuccessfully with exit code {}",
                     command, exit_code
                 ))
-            } else {
-                Err(format!("{:?} finished unsuccessfully", command))
-            }
+            })
         } else {
-            Ok(())
+            // Like shells do, report termination by a signal as 128 + the signal number.
+            Err(Error {
+                exit_code: 128 + status.signal().unwrap_or(0),
+                ..Error::from(format!("{:?} finished unsuccessfully", command))
+            })
         }
     } else {
-        Err("No command to run found.".to_string())
+        Ok(())
     }
 }
 
-/// This function reads all of the contents of the given file.
-fn read_file(file: &str) -> io::Result<Vec<u8>> {
-    let mut content = vec![];
+/// Builds the command to run, preloading the given library.
+///
+/// The command inherits the given environment, unless `--clean-env` is used.
+fn build_command<P, E>(config: &Config, preload_path: P, parent_env: E) -> Result<Command, String>
+where
+    P: AsRef<Path>,
+    E: IntoIterator<Item = (OsString, OsString)>,
+{
+    let args = match &config.command {
+        Some(args) if !args.is_empty() => args,
+        _ => return Err("No command to run found.".to_string()),
+    };
 
-    if file == "-" {
-        stdin().read_to_end(&mut content)?;
-    } else {
-        File::open(file)?.read_to_end(&mut content)?;
+    let mut command = Command::new(&args[0]);
+
+    for arg in args.iter().skip(1) {
+        command.arg(arg);
     }
 
-    Ok(content)
+    command.env_clear();
+    command.envs(command_env(config, preload_path.as_ref(), parent_env)?);
+
+    if config.capture {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+
+    Ok(command)
 }
 
-/// This function writes the output to the given file.
-fn write_file(file: &str, output: &[u8]) -> io::Result<()> {
-    if file == "-" {
-        stdout().write_all(output)?;
+/// Returns the environment of the command, preloading the stubs at the given path.
+///
+/// The variables of the given parent environment are inherited, except for `PATH` only with
+/// `--clean-env`.
+fn command_env<E>(
+    config: &Config,
+    preload_path: &Path,
+    parent_env: E,
+) -> Result<Vec<(OsString, OsString)>, String>
+where
+    E: IntoIterator<Item = (OsString, OsString)>,
+{
+    // The command still needs to be found without the other variables.
+    let mut vars: Vec<_> = parent_env
+        .into_iter()
+        .filter(|(key, _)| !config.clean_env || key == "PATH")
+        .collect();
+
+    for (key, value) in &config.env {
+        vars.push((key.into(), value.into()));
+    }
+
+    vars.push(("LD_PRELOAD".into(), preload_list(config, preload_path)?));
+
+    Ok(vars)
+}
+
+/// Returns the value of `LD_PRELOAD` preloading the stubs at the given path.
+///
+/// The libraries given with `--preload-lib` are checked to exist and added in front of or after
+/// the stubs.
+fn preload_list(config: &Config, preload_path: &Path) -> Result<OsString, String> {
+    let mut paths = Vec::new();
+
+    for path in &config.preload_libs {
+        if !path.is_file() {
+            return Err(format!("{}: The library to preload does not exist", path.display()));
+        }
+
+        // The dynamic linker splits the list at colons and whitespace.
+        if path.to_string_lossy().contains(|c: char| c == ':' || c.is_whitespace()) {
+            return Err(format!(
+                "{}: The library to preload cannot contain colons or whitespace",
+                path.display()
+            ));
+        }
+
+        paths.push(absolute_preload_path(path));
+    }
+
+    let stubs = absolute_preload_path(preload_path);
+    if config.preload_libs_first {
+        paths.push(stubs);
     } else {
-        File::create(file)?.write_all(output)?;
+        paths.insert(0, stubs);
     }
 
-    Ok(())
+    let mut list = OsString::new();
+    for (i, path) in paths.iter().enumerate() {
+        if i != 0 {
+            list.push(":");
+        }
+        list.push(path);
+    }
+
+    Ok(list)
 }
 
-/// Represents a configuration for the program.
-#[derive(Debug)]
-pub struct Config {
-    /// The file to read the input from. - for stdin.
-    input_file: String,
-    /// The file to write the output to. - for stdout.
-    output_file: String,
-    /// Whether to stop after changing the C code.
-    output_to_c: bool,
-    /// The file to use for debug output. - for stderr.
-    debug_output: String,
-    /// The C compiler to use.
-    c_compiler: String,
-    /// Whether to stop after creating the shared object file.
-    create_shared_object: bool,
-    /// The command to run.
-    command: Option<Vec<String>>,
+/// Returns the path of the library to preload, made absolute if it is relative.
+///
+/// The dynamic linker does not load relative paths reliably, for example if the command changes
+/// its directory.
+fn absolute_preload_path(path: &Path) -> PathBuf {
+    match env::current_dir() {
+        Ok(dir) if path.is_relative() && !path.as_os_str().is_empty() => dir.join(path),
+        _ => path.to_path_buf(),
+    }
 }
 
-/// Returns a configuration for this program.
-fn get_config() -> Config {
-    let matches = App::new("ldpsc")
-        .version(&crate_version!()[..])
-        .author(crate_authors!())
-        .about("ldpsc (ld preload stub creator) creates stubs to preload as shared libraries.")
-        .arg(Arg::with_name("input")
-            .required(false)
-            .takes_value(true)
-            .short("i")
-            .long("input")
-            .help("The input file")
-            .long_help("Specifies the input file where the stubs are located. By default - is used to read from stdin."))
-        .arg(Arg::with_name("output-file")
-            .required(false)
+/// The output streams of a command, if they were captured.
+#[derive(Debug, Default)]
+struct CapturedOutput {
+    /// Everything the command wrote to stdout.
+    stdout: Vec<u8>,
+    /// Everything the command wrote to stderr.
+    stderr: Vec<u8>,
+}
+
+/// Executes the command and waits for it to finish.
+fn execute_command(
+    config: &Config,
+    command: &mut Command,
+) -> Result<(ExitStatus, Option<CapturedOutput>), String> {
+    let mut child = command
+        .spawn()
+        .map_err(|err| format!("Running {:?} failed: {}", command, err))?;
+
+    // The pipes are read in the background, so a full pipe cannot block the command.
+    let stdout_reader = child.stdout.take().map(read_in_background);
+    let stderr_reader = child.stderr.take().map(read_in_background);
+
+    let status = if let Some(timeout) = config.timeout {
+        let status = wait_timeout(&mut child, timeout)
+            .map_err(|err| format!("Waiting for {:?} failed: {}", command, err))?;
+
+        if let Some(status) = status {
+            status
+        } else {
+            child
+                .kill()
+                .and_then(|_| child.wait())
+                .map_err(|err| format!("Killing {:?} failed: {}", command, err))?;
+
+            return Err(format!(
+                "{:?} timed out after {} seconds",
+                command,
+                timeout.as_secs()
+            ));
+        }
+    } else {
+        child
+            .wait()
+            .map_err(|err| format!("Waiting for {:?} failed: {}", command, err))?
+    };
+
+    let captured = match (stdout_reader, stderr_reader) {
+        (Some(stdout_reader), Some(stderr_reader)) => {
+            let join = |reader: thread::JoinHandle<io::Result<Vec<u8>>>| {
+                reader
+                    .join()
+                    .expect("Reading the output panicked.")
+                    .map_err(|err| format!("Reading the output of {:?} failed: {}", command, err))
+            };
+
+            Some(CapturedOutput {
+                stdout: join(stdout_reader)?,
+                stderr: join(stderr_reader)?,
+            })
+        }
+        _ => None,
+    };
+
+    Ok((status, captured))
+}
+
+/// Reads everything from the given reader on a separate thread.
+fn read_in_background<R: Read + Send + 'static>(
+    mut reader: R,
+) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut content = vec![];
+        reader.read_to_end(&mut content)?;
+        Ok(content)
+    })
+}
+
+/// Writes the captured output to the configured files or labeled to stdout.
+fn write_captured_output(config: &Config, captured: &CapturedOutput) -> Result<(), String> {
+    let streams = [
+        ("stdout", &config.stdout_file, &captured.stdout),
+        ("stderr", &config.stderr_file, &captured.stderr),
+    ];
+
+    for (label, file, content) in &streams {
+        if let Some(file) = file {
+            write_file(file, content).map_err(|err| format!("{}: {}", file, err))?;
+        } else {
+            let mut section = format!("=== {} ===\n", label).into_bytes();
+            section.extend_from_slice(content);
+
+            write_file("-", &section).map_err(|err| format!("Writing {} failed: {}", label, err))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Waits for the child to exit, but for no longer than the given timeout.
+///
+/// Returns `None` if the child is still running after the timeout.
+fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let start = Instant::now();
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        if start.elapsed() >= timeout {
+            return Ok(None);
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// This function reads all of the contents of the given file, naming it in errors.
+fn read_file(file: &str) -> Result<Vec<u8>, String> {
+    if file == "-" {
+        read_labeled(stdin(), "stdin")
+    } else {
+        let reader = File::open(file).map_err(|err| format!("{}: {}", file, err))?;
+        read_labeled(reader, file)
+    }
+}
+
+/// Reads everything from the given reader, labeling errors with the given name of the input.
+fn read_labeled<R: Read>(mut reader: R, label: &str) -> Result<Vec<u8>, String> {
+    let mut content = vec![];
+
+    reader
+        .read_to_end(&mut content)
+        .map_err(|err| format!("{}: {}", label, err))?;
+
+    Ok(content)
+}
+
+/// This function writes the output to the given file.
+fn write_file(file: &str, output: &[u8]) -> io::Result<()> {
+    if file == "-" {
+        stdout().write_all(output)?;
+    } else {
+        File::create(file)?.write_all(output)?;
+    }
+
+    Ok(())
+}
+
+/// Represents a configuration for the program.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The file to read the input from. - for stdin.
+    input_file: String,
+    /// The file to write the output to. - for stdout.
+    output_file: String,
+    /// Whether to stop after changing the C code.
+    output_to_c: bool,
+    /// The file to use for debug output. - for stderr.
+    debug_output: String,
+    /// The C compiler to use.
+    c_compiler: String,
+    /// Whether to stop after creating the shared object file.
+    create_shared_object: bool,
+    /// The command to run.
+    command: Option<Vec<String>>,
+    /// The maximum time the command may run for.
+    timeout: Option<Duration>,
+    /// Additional environment variables for the command.
+    env: Vec<(String, String)>,
+    /// Whether to capture the output of the command.
+    capture: bool,
+    /// The file to write the captured stdout to.
+    stdout_file: Option<String>,
+    /// The file to write the captured stderr to.
+    stderr_file: Option<String>,
+    /// Additional flags for the C compiler.
+    cc_flags: Vec<String>,
+    /// Whether to report the progress on stderr.
+    verbose: bool,
+    /// The directory to create the temporary directory in.
+    temp_dir: Option<PathBuf>,
+    /// The format used to log pointers.
+    pointer_format: PointerFormat,
+    /// The point at which calls are logged.
+    log_point: LogPoint,
+    /// Whether to log `_Bool` values as `true` and `false`.
+    bool_names: bool,
+    /// The text every log line starts with.
+    log_prefix: String,
+    /// Whether functions that cannot be interposed are an error.
+    strict: bool,
+    /// The names of the original functions to use instead of the stubbed names.
+    aliases: HashMap<String, String>,
+    /// Whether to log a line when the program starts.
+    log_banner: bool,
+    /// The failures returned by stubs instead of calling the original functions.
+    failures: HashMap<String, Failure>,
+    /// The percentages of calls in which stubs fail.
+    fail_rates: HashMap<String, u32>,
+    /// Whether to only list the parsed functions.
+    list: bool,
+    /// Whether to compile the shared object with hardening flags.
+    hardened: bool,
+    /// Whether the command is run without inheriting the environment.
+    clean_env: bool,
+    /// The file to write a Makefile compiling the C code to.
+    makefile: Option<String>,
+    /// Whether to run the C preprocessor on the input.
+    preprocess: bool,
+    /// Whether to skip unrecognized input instead of failing.
+    lenient: bool,
+    /// The prefix of the variables holding the original functions.
+    orig_prefix: String,
+    /// The directory to write the C code and the shared object to.
+    output_dir: Option<String>,
+    /// The name of the files written to the output directory.
+    name: Option<String>,
+    /// The text logged for pointer results that are NULL.
+    null_marker: Option<String>,
+    /// Whether to only list the supported types.
+    list_supported_types: bool,
+    /// Whether to write a separate shared object for each function to the output directory.
+    split_by_function: bool,
+    /// Whether to only check that a stub can be generated and compiled.
+    self_test: bool,
+    /// The radix integers are logged in.
+    int_radix: IntRadix,
+    /// The conditions under which stubs fail.
+    fail_conditions: HashMap<String, Condition>,
+    /// Whether the stubs are exported regardless of the default symbol visibility.
+    export_symbols: bool,
+    /// Whether warnings and progress messages are suppressed.
+    quiet: bool,
+    /// The directory each thread writes its own debug output file to.
+    per_thread_log: Option<String>,
+    /// Whether `_GNU_SOURCE` is defined on the command line of the compiler instead of the code.
+    no_header_defines: bool,
+    /// The format calls are logged in.
+    log_format: LogFormat,
+    /// The file the mapping of function ids to names is written to.
+    schema: Option<String>,
+    /// Whether each stub is preceded by a comment containing its prototype.
+    annotate: bool,
+    /// The time limit for the C compiler.
+    compiler_timeout: Option<Duration>,
+    /// The layout of log lines.
+    template: Option<Vec<TemplatePart>>,
+    /// The format errors are reported in.
+    error_format: ErrorFormat,
+    /// The file the calls are recorded in for replaying them.
+    record: Option<String>,
+    /// The C standard the shared object file is compiled with.
+    c_standard: String,
+    /// The header declaring the hooks called after the original functions.
+    hook_header: Option<String>,
+    /// The number of calls of each function that are logged.
+    max_calls: Option<u64>,
+    /// Whether to ask which functions to stub.
+    interactive: bool,
+    /// The functions that are stubbed, if not all of them.
+    selected_functions: Option<Vec<String>>,
+    /// The maximum number of bytes of annotated buffers that are logged, if they are logged.
+    hexdump_buffers: Option<u64>,
+    /// Whether stubs return zero if the original function is missing instead of aborting.
+    null_on_missing: bool,
+    /// Whether the C code is written in a normalized form.
+    canonical: bool,
+    /// The word size of the target the shared object file is compiled for.
+    bits: Option<u32>,
+    /// Whether the strings that `char **` parameters point to are logged.
+    deref_strings: bool,
+    /// The functions whose stubs abort the program after logging the call.
+    abort_functions: Vec<String>,
+    /// The maximum number of frames of the backtraces logged with each call, if they are logged.
+    backtrace: Option<u32>,
+    /// The file the names of the interposed symbols are written to, if any.
+    symbol_list: Option<String>,
+    /// The handle the original functions are looked up with.
+    dlsym_handle: DlsymHandle,
+    /// Whether each log line starts with the sequence number of the call.
+    sequence: bool,
+    /// Whether the generated code is compiled as C++.
+    cxx: bool,
+    /// The directory compiled shared objects are cached in, if any.
+    cache_dir: Option<PathBuf>,
+    /// The functions whose results are not logged.
+    no_result_functions: Vec<String>,
+    /// Whether the output is flushed after each log line.
+    flush: bool,
+    /// Other libraries to preload together with the stubs.
+    preload_libs: Vec<PathBuf>,
+    /// Whether the other libraries are preloaded before the stubs.
+    preload_libs_first: bool,
+    /// The file to write a shell script compiling and preloading the C code to.
+    script: Option<String>,
+    /// The functions taking a `va_list` that the variable arguments of stubs are forwarded to.
+    va_list_variants: HashMap<String, String>,
+}
+
+/// Returns a configuration for this program.
+fn get_config() -> Config {
+    parse_config(env::args_os()).unwrap_or_else(|err| err.exit())
+}
+
+/// The number of bytes logged of each buffer with `--hexdump-buffers` by default.
+const DEFAULT_HEXDUMP_CAP: u64 = 64;
+
+/// The standard the generated code is compiled with as C++ by default.
+const DEFAULT_CXX_STANDARD: &str = "gnu++11";
+
+/// The number of frames logged of each backtrace with `--backtrace` by default.
+const DEFAULT_BACKTRACE_DEPTH: u32 = 16;
+
+/// Returns the command line interface of this program.
+///
+/// The names of the arguments are their long names, which are also the keys in configuration
+/// files.
+fn app() -> App<'static, 'static> {
+    App::new("ldpsc")
+        .version(crate_version!())
+        .author(crate_authors!())
+        .about("ldpsc (ld preload stub creator) creates stubs to preload as shared libraries.")
+        .setting(AppSettings::AllArgsOverrideSelf)
+        .arg(Arg::with_name("config")
+            .required(false)
+            .takes_value(true)
+            .long("config")
+            .help("The configuration file to use")
+            .long_help("Reads default values for the options from the given TOML file. Each key is the long name of an option. By default ldpsc.toml is used if it exists."))
+        .arg(Arg::with_name("input")
+            .required(false)
+            .takes_value(true)
+            .short("i")
+            .long("input")
+            .help("The input file")
+            .long_help("Specifies the input file where the stubs are located. By default - is used to read from stdin."))
+        .arg(Arg::with_name("output")
+            .required(false)
             .takes_value(true)
             .short("o")
             .long("output")
@@ -208,11 +1178,10 @@ fn get_config() -> Config {
         .arg(Arg::with_name("c-compiler")
             .required(false)
             .takes_value(true)
-            .conflicts_with("output-c")
             .short("C")
             .long("c-compiler")
             .help("The C compiler to use")
-            .long_help("The C compiler to use for the creation of the shared object file. By default cc is used."))
+            .long_help("The C compiler to use for the creation of the shared object file. By default cc is used. With --output-c, it is only used in the Makefile written with --emit-makefile."))
         .arg(Arg::with_name("create-so")
             .required(false)
             .conflicts_with("output-c")
@@ -220,18 +1189,544 @@ fn get_config() -> Config {
             .long("create-so")
             .help("Output shared object")
             .long_help("Instead of executing a program with the shared object, stop after creating it."))
+        .arg(Arg::with_name("timeout")
+            .required(false)
+            .takes_value(true)
+            .conflicts_with("output-c")
+            .conflicts_with("create-so")
+            .short("t")
+            .long("timeout")
+            .validator(|value| value.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+            .help("The time limit for the command in seconds")
+            .long_help("If the command runs for longer than the given number of seconds, it is killed. By default there is no time limit."))
         .arg(Arg::with_name("command")
             .required(true)
             .multiple(true)
             .conflicts_with("output-c")
             .conflicts_with("create-so")
+            .conflicts_with("list")
+            .conflicts_with("output-dir")
+            .conflicts_with("list-supported-types")
+            .conflicts_with("self-test")
             .help("The command to run")
             .long_help("The command to run with the preloaded shared object. Only used when the --output-c and --create-so are not used."))
-        .get_matches();
+        .arg(Arg::with_name("env")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .conflicts_with("output-c")
+            .conflicts_with("create-so")
+            .short("e")
+            .long("env")
+            .value_name("KEY=VALUE")
+            .validator(|value| parse_env_definition(&value).map(|_| ()))
+            .help("Sets an environment variable for the command")
+            .long_help("Sets the given environment variable for the command. Can be used multiple times."))
+        .arg(Arg::with_name("capture")
+            .required(false)
+            .conflicts_with("output-c")
+            .conflicts_with("create-so")
+            .long("capture")
+            .help("Capture the output of the command")
+            .long_help("Captures stdout and stderr of the command separately and prints them in labeled sections once the command finished."))
+        .arg(Arg::with_name("stdout-file")
+            .required(false)
+            .takes_value(true)
+            .requires("capture")
+            .long("stdout-file")
+            .help("The file to write the captured stdout to")
+            .long_help("Instead of printing the captured stdout of the command, write it to the given file. Requires --capture."))
+        .arg(Arg::with_name("stderr-file")
+            .required(false)
+            .takes_value(true)
+            .requires("capture")
+            .long("stderr-file")
+            .help("The file to write the captured stderr to")
+            .long_help("Instead of printing the captured stderr of the command, write it to the given file. Requires --capture."))
+        .arg(Arg::with_name("cc-flag")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .allow_hyphen_values(true)
+            .long("cc-flag")
+            .help("Passes a flag to the C compiler")
+            .long_help("Passes the given flag to the C compiler when creating the shared object file. With --output-c, it is only used in the Makefile written with --emit-makefile. Can be used multiple times."))
+        .arg(Arg::with_name("verbose")
+            .required(false)
+            .short("v")
+            .long("verbose")
+            .help("Report the progress")
+            .long_help("Reports the number of parsed functions, the compiler invocation, the temporary directory and the command to run on stderr."))
+        .arg(Arg::with_name("temp-dir")
+            .required(false)
+            .takes_value(true)
+            .conflicts_with("output-c")
+            .long("temp-dir")
+            .help("The directory for temporary files")
+            .long_help("The shared object is compiled in a temporary directory that is created in the given directory. By default the system's temporary directory is used."))
+        .arg(Arg::with_name("pointer-format")
+            .required(false)
+            .takes_value(true)
+            .possible_values(&["native", "hex"])
+            .long("pointer-format")
+            .help("The format used to log pointers")
+            .long_help("With native, pointers are logged with %p, which looks different depending on the C library. With hex, they are logged as 16 digit hexadecimal numbers, including NULL. By default native is used."))
+        .arg(Arg::with_name("log-point")
+            .required(false)
+            .takes_value(true)
+            .possible_values(&["entry", "exit", "both"])
+            .long("log-point")
+            .help("When calls are logged")
+            .long_help("With entry, calls are logged before calling the original function, which shows calls that never return. With exit, they are logged afterwards including the result. With both, they are logged twice. By default exit is used."))
+        .arg(Arg::with_name("bool-names")
+            .required(false)
+            .long("bool-names")
+            .help("Log _Bool values as true and false")
+            .long_help("Logs _Bool values as true and false instead of 1 and 0."))
+        .arg(Arg::with_name("log-prefix")
+            .required(false)
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .long("log-prefix")
+            .help("The text every log line starts with")
+            .long_help("Every log line starts with the given text, which makes it possible to tell apart multiple programs logging to the same file."))
+        .arg(Arg::with_name("strict")
+            .required(false)
+            .long("strict")
+            .help("Reject functions that cannot be interposed")
+            .long_help("Functions like main are never called through the dynamic linker, so their stubs are never called. Functions declared without a prototype, like int foo();, are assumed to take no arguments, so their stubs do not forward any. By default only a warning is printed for them, with this flag they are an error."))
+        .arg(Arg::with_name("alias")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("alias")
+            .value_name("NAME=REALNAME")
+            .validator(|value| parse_key_value(&value).map(|_| ()))
+            .help("Forwards calls of a stub to a different function")
+            .long_help("The stub for NAME forwards its calls to the function REALNAME instead of the original NAME. Can be used multiple times."))
+        .arg(Arg::with_name("log-banner")
+            .required(false)
+            .long("log-banner")
+            .help("Log a line when the program starts")
+            .long_help("Logs a line with the process id when the program starts, which separates the runs when appending to the same file."))
+        .arg(Arg::with_name("fail")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .allow_hyphen_values(true)
+            .long("fail")
+            .value_name("NAME=RETVAL:ERRNO")
+            .validator(|value| parse_failure(&value).map(|_| ()))
+            .help("Makes a stub fail instead of calling the original function")
+            .long_help("The stub for NAME sets errno to ERRNO and returns RETVAL without calling the original function. ERRNO can be a number or a name like ENOENT. RETVAL is left empty for functions returning void. It must be a constant of the return type: an integer or a character constant for integers, a floating point number for floating point types, or NULL, 0 or a string literal for pointers. With --fail-rate and --when, the stub only fails in some of the calls. Can be used multiple times."))
+        .arg(Arg::with_name("fail-rate")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("fail-rate")
+            .value_name("NAME=PERCENT")
+            .validator(|value| parse_fail_rate(&value).map(|_| ()))
+            .help("Makes a stub fail only in some of the calls")
+            .long_help("The stub for NAME fails as given with --fail in PERCENT percent of the calls and calls the original function otherwise. PERCENT is a whole number from 0 to 100. Can be used multiple times."))
+        .arg(Arg::with_name("list")
+            .required(false)
+            .long("list")
+            .conflicts_with("output-c")
+            .conflicts_with("create-so")
+            .help("List the parsed functions")
+            .long_help("Instead of generating any code list the functions found in the input, one per line with their name, number of parameters and return type separated by tabs."))
+        .arg(Arg::with_name("hardened")
+            .required(false)
+            .long("hardened")
+            .help("Compile with hardening flags")
+            .long_help("Compiles the shared object with the hardening flags -z now -z relro -fstack-protector-strong. They are passed before the flags given with --cc-flag."))
+        .arg(Arg::with_name("clean-env")
+            .required(false)
+            .long("clean-env")
+            .conflicts_with("output-c")
+            .conflicts_with("create-so")
+            .help("Run the command in a clean environment")
+            .long_help("Runs the command without inheriting the environment. Only PATH, LD_PRELOAD and the variables given with --env are set."))
+        .arg(Arg::with_name("emit-makefile")
+            .required(false)
+            .takes_value(true)
+            .requires("output-c")
+            .long("emit-makefile")
+            .value_name("PATH")
+            .help("Write a Makefile compiling the C code")
+            .long_help("Writes a Makefile to PATH that compiles the C code written with --output-c to a shared object with the same flags ldpsc would use. The C code needs to be written to a file. The compiler and the additional flags can be changed with the CC and CFLAGS variables of make."))
+        .arg(Arg::with_name("preprocess")
+            .required(false)
+            .long("preprocess")
+            .help("Run the C preprocessor on the input")
+            .long_help("Runs the input through the preprocessor of the C compiler before parsing it, which resolves includes and macros. The flags given with --cc-flag are passed to the preprocessor, so they can add include paths and define macros."))
+        .arg(Arg::with_name("lenient")
+            .required(false)
+            .long("lenient")
+            .help("Skip unrecognized input")
+            .long_help("Skips everything in the input that is not recognized as a function prototype, like variable declarations and struct definitions, instead of failing. A warning reports how many items were skipped. This is useful together with --preprocess."))
+        .arg(Arg::with_name("orig-prefix")
+            .required(false)
+            .takes_value(true)
+            .long("orig-prefix")
+            .validator(|value| validate_identifier(&value))
+            .help("The prefix of the variables holding the original functions")
+            .long_help("The prefix of the names of the function pointers to the original functions in the stubs. By default original_ is used."))
+        .arg(Arg::with_name("output-dir")
+            .required(false)
+            .takes_value(true)
+            .long("output-dir")
+            .value_name("DIR")
+            .conflicts_with("output-c")
+            .conflicts_with("create-so")
+            .conflicts_with("output")
+            .help("Write the C code and the shared object to a directory")
+            .long_help("Writes both the C code and the shared object to DIR instead of running a command. The files are named after the input file or the name given with --name. DIR is created if it does not exist."))
+        .arg(Arg::with_name("name")
+            .required(false)
+            .takes_value(true)
+            .long("name")
+            .requires("output-dir")
+            .help("The name of the files in the output directory")
+            .long_help("The name of the files written to the directory given with --output-dir, without the extension. By default the name of the input file is used, or a name unique to the run when reading from stdin. Characters other than letters, digits, '-', '_' and '.' are replaced with '_'."))
+        .arg(Arg::with_name("null-marker")
+            .required(false)
+            .takes_value(true)
+            .long("null-marker")
+            .value_name("TEXT")
+            .help("The text logged for pointer results that are NULL")
+            .long_help("Pointer results that are NULL are logged as TEXT instead of with the pointer format, which makes failures of functions like fopen easy to spot."))
+        .arg(Arg::with_name("list-supported-types")
+            .required(false)
+            .long("list-supported-types")
+            .conflicts_with("output-c")
+            .conflicts_with("create-so")
+            .conflicts_with("list")
+            .help("List the supported types")
+            .long_help("Instead of reading any input list the supported type specifiers, one per line with the format their values are logged with. Types without their own format are logged with a fallback, pointers are listed last."))
+        .arg(Arg::with_name("split-by-function")
+            .required(false)
+            .long("split-by-function")
+            .requires("output-dir")
+            .help("Write a separate shared object for each function")
+            .long_help("Writes the C code and the shared object for each function to separate files in the directory given with --output-dir, so they can be preloaded individually. The files are named after the function, prefixed with the name of the input file or the name given with --name and a hyphen."))
+        .arg(Arg::with_name("self-test")
+            .required(false)
+            .long("self-test")
+            .conflicts_with("output-c")
+            .conflicts_with("create-so")
+            .conflicts_with("list")
+            .conflicts_with("list-supported-types")
+            .conflicts_with("output-dir")
+            .help("Check that stubs can be compiled")
+            .long_help("Instead of reading any input generate and compile a stub for puts to check that the C compiler works with the given options."))
+        .arg(Arg::with_name("int-radix")
+            .required(false)
+            .takes_value(true)
+            .possible_values(&["decimal", "hex"])
+            .long("int-radix")
+            .help("The radix integers are logged in")
+            .long_help("With decimal, integers are logged as decimal numbers. With hex, they are logged as hexadecimal numbers prefixed with 0x, which makes flags readable. This applies to both arguments and results. By default decimal is used."))
+        .arg(Arg::with_name("when")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("when")
+            .value_name("NAME:PARAM==VALUE")
+            .validator(|value| parse_condition(&value).map(|_| ()))
+            .help("Makes a stub fail only if a parameter has a value")
+            .long_help("The stub for NAME fails as given with --fail only if its parameter PARAM equals VALUE and calls the original function otherwise. Strings are compared by their content, other values are compared with ==. Can be used multiple times."))
+        .arg(Arg::with_name("export-symbols")
+            .required(false)
+            .long("export-symbols")
+            .help("Keep the stubs visible")
+            .long_help("Marks every stub with __attribute__((visibility(\"default\"))), so it can still interpose the original function when compiling with -fvisibility=hidden."))
+        .arg(Arg::with_name("quiet")
+            .required(false)
+            .short("q")
+            .long("quiet")
+            .conflicts_with("verbose")
+            .help("Only report errors")
+            .long_help("Suppresses the warnings and progress messages of ldpsc. Errors and the output of the command are still written."))
+        .arg(Arg::with_name("per-thread-log")
+            .required(false)
+            .takes_value(true)
+            .long("per-thread-log")
+            .value_name("DIR")
+            .conflicts_with("debug-output")
+            .help("Write the debug output of each thread to its own file")
+            .long_help("Debug messages of the calls are written to DIR/thread-<tid>.log, where <tid> is the id of the calling thread. The directory is created when the shared object is loaded."))
+        .arg(Arg::with_name("no-header-defines")
+            .required(false)
+            .long("no-header-defines")
+            .help("Do not define _GNU_SOURCE in the C code")
+            .long_help("Leaves out the definition of _GNU_SOURCE at the start of the generated C code, so it can be included into code defining its own feature test macros. The compiler is passed -D_GNU_SOURCE instead."))
+        .arg(Arg::with_name("log-format")
+            .required(false)
+            .takes_value(true)
+            .possible_values(&["text", "binary"])
+            .long("log-format")
+            .conflicts_with_all(&["log-banner", "per-thread-log"])
+            .help("The format calls are logged in")
+            .long_help("With text, calls are logged as readable lines. With binary, each call is logged as a record of 64 bit words written with write: the function id, the number of arguments, the number of following words, the raw arguments and the result if it is logged. Values larger than a word are truncated. By default text is used."))
+        .arg(Arg::with_name("emit-schema")
+            .required(false)
+            .takes_value(true)
+            .long("emit-schema")
+            .value_name("FILE")
+            .help("Write the function ids used in binary logs to a file")
+            .long_help("Writes a line for each function to FILE, containing its id in binary logs, its name, its return type and its parameter types separated by tabs."))
+        .arg(Arg::with_name("annotate")
+            .required(false)
+            .long("annotate")
+            .help("Annotate the stubs with their prototype")
+            .long_help("Writes a comment with the prototype each stub was generated from in front of it."))
+        .arg(Arg::with_name("compiler-timeout")
+            .required(false)
+            .takes_value(true)
+            .long("compiler-timeout")
+            .value_name("SECONDS")
+            .validator(|value| value.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+            .help("The time limit for the C compiler in seconds")
+            .long_help("If the C compiler runs for longer than the given number of seconds, it is killed. By default there is no time limit."))
+        .arg(Arg::with_name("template")
+            .required(false)
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .long("template")
+            .validator(|value| parse_template(&value).map(|_| ()))
+            .help("The layout of log lines")
+            .long_help("Text logs use the given template for every line. The placeholders {fn}, {args}, {ret}, {pid} and {tid} are replaced by the name of the function, its arguments, its result, the process id and the thread id. {ret} is empty if the result is not logged. By default {ret} = {fn}({args}) is used."))
+        .arg(Arg::with_name("error-format")
+            .required(false)
+            .takes_value(true)
+            .possible_values(&["text", "json"])
+            .long("error-format")
+            .help("The format errors are reported in")
+            .long_help("With json, an error is reported on stderr as a JSON object with the stage it happened in (setup, parse, compile or run) and its message. Errors in the input also have the line and column they were found at. By default text is used."))
+        .arg(Arg::with_name("record")
+            .required(false)
+            .takes_value(true)
+            .long("record")
+            .value_name("FILE")
+            .help("Record the calls with their raw arguments to a file")
+            .long_help("Appends a record to FILE after each call of an original function, so the calls can be replayed. A record consists of the function id and the number of fields as 64 bit words, followed by the fields. Each field is its size as a 64 bit word followed by that many bytes. The fields are the parameters, the contents of the buffers and the result. A pointer parameter is recorded as a buffer if it is annotated with a comment like /* length: count */, where count is the parameter holding its length. The function ids are written with --emit-schema."))
+        .arg(Arg::with_name("std")
+            .required(false)
+            .takes_value(true)
+            .long("std")
+            .value_name("STANDARD")
+            .default_value("gnu11")
+            .help("The C standard to compile with")
+            .long_help("Passes -std=STANDARD to the C compiler when creating the shared object file. The generated code requires at least C11 for some types, so the default is gnu11."))
+        .arg(Arg::with_name("hook-header")
+            .required(false)
+            .takes_value(true)
+            .long("hook-header")
+            .value_name("FILE")
+            .help("Call hooks declared in a header after the original functions")
+            .long_help("Includes FILE in the C code. After calling the original function foo, the stub calls ldpsc_hook_foo with the arguments and a pointer to the result, if there is one, so the hook can change it. Hooks are declared weak, so stubs of functions without a hook behave normally. Hooks can be defined in FILE or in another library that is loaded."))
+        .arg(Arg::with_name("max-calls")
+            .required(false)
+            .takes_value(true)
+            .long("max-calls")
+            .value_name("N")
+            .validator(|value| value.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+            .help("Only log the first N calls of each function")
+            .long_help("Each stub counts its calls and stops logging after the first N calls. Later calls are still forwarded to the original function. By default all calls are logged."))
+        .arg(Arg::with_name("interactive")
+            .required(false)
+            .long("interactive")
+            .help("Ask which functions to stub")
+            .long_help("Asks on the controlling terminal for each parsed function whether it should be stubbed. This works when the input is piped to ldpsc. Answers starting with n exclude the function, all others include it. Without a controlling terminal, all functions are stubbed."))
+        .arg(Arg::with_name("hexdump-buffers")
+            .required(false)
+            .long("hexdump-buffers")
+            .help("Log the contents of buffers as hex")
+            .long_help("Appends the bytes of each buffer to the log line in hex. A pointer parameter is a buffer if it is annotated with a comment like /* length: count */, where count is the parameter holding its length. At most the number of bytes given with --hexdump-cap are logged. Binary logs do not contain the contents of buffers."))
+        .arg(Arg::with_name("hexdump-cap")
+            .required(false)
+            .takes_value(true)
+            .long("hexdump-cap")
+            .value_name("BYTES")
+            .requires("hexdump-buffers")
+            .validator(|value| value.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+            .help("The maximum number of bytes logged of each buffer")
+            .long_help("Limits the number of bytes logged of each buffer with --hexdump-buffers. By default at most 64 bytes are logged."))
+        .arg(Arg::with_name("null-on-missing")
+            .required(false)
+            .long("null-on-missing")
+            .help("Return zero if the original function is missing")
+            .long_help("If the original function cannot be found, the stub returns zero or NULL instead of aborting the program. This allows using the same stubs on systems where some of the functions do not exist. The error is still logged."))
+        .arg(Arg::with_name("canonical")
+            .required(false)
+            .long("canonical")
+            .help("Write the C code in a normalized form")
+            .long_help("Sorts the includes and the stubs by name and writes the prototypes with --annotate the same way as the stubs. Inputs declaring the same functions then produce the same C code, which makes it easy to compare."))
+        .arg(Arg::with_name("bits")
+            .required(false)
+            .takes_value(true)
+            .long("bits")
+            .possible_values(&["32", "64"])
+            .help("Compile for a 32 or 64 bit target")
+            .long_help("Passes -m32 or -m64 to the C compiler when creating the shared object file, so the stubs can be preloaded into 32 bit programs on a 64 bit system. This requires a C library for the target. By default the compiler's default target is used."))
+        .arg(Arg::with_name("deref-strings")
+            .required(false)
+            .long("deref-strings")
+            .help("Log the strings that char ** values point to")
+            .long_help("Logs values of type char ** as the string they point to instead of as a pointer. This shows the results of functions like getline that return strings through a parameter. NULL pointers and pointers to NULL are logged as (null)."))
+        .arg(Arg::with_name("abort")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("abort")
+            .value_name("NAME,...")
+            .help("Makes stubs abort the program instead of calling the original function")
+            .long_help("The stubs of the given functions log the call and then abort the program, so unexpected calls can be found with a debugger or a core dump. Several functions can be separated by commas. Can be used multiple times."))
+        .arg(Arg::with_name("backtrace")
+            .required(false)
+            .long("backtrace")
+            .help("Log a backtrace with each call")
+            .long_help("Writes the backtrace of each call to the debug output after the log line, so it shows where the function is called from. The stubs are compiled with -rdynamic, so the names of more functions can be resolved. At most the number of frames given with --backtrace-depth are logged. With binary logs, the backtraces are written to stderr."))
+        .arg(Arg::with_name("backtrace-depth")
+            .required(false)
+            .takes_value(true)
+            .long("backtrace-depth")
+            .value_name("N")
+            .requires("backtrace")
+            .validator(|value| {
+                match value.parse::<u32>() {
+                    Ok(depth) if depth > 0 => Ok(()),
+                    _ => Err(format!("Expected a positive number of frames, found {:?}", value)),
+                }
+            })
+            .help("The maximum number of frames of each backtrace")
+            .long_help("Limits the number of frames of the backtraces logged with --backtrace. By default at most 16 frames are logged."))
+        .arg(Arg::with_name("symbol-list")
+            .required(false)
+            .takes_value(true)
+            .long("symbol-list")
+            .value_name("FILE")
+            .help("Write the names of the interposed symbols to a file")
+            .long_help("Writes the name of each symbol the generated stubs interpose to FILE, one per line. This can be compared to the output of LD_DEBUG=symbols to check which symbols are interposed."))
+        .arg(Arg::with_name("dlsym-handle")
+            .required(false)
+            .takes_value(true)
+            .possible_values(&["next", "default"])
+            .long("dlsym-handle")
+            .help("The handle the original functions are looked up with")
+            .long_help("With next, the original functions are looked up with RTLD_NEXT in the libraries loaded after the stubs. With default, they are looked up with RTLD_DEFAULT in the global scope. That finds the stubs themselves if they are preloaded, in which case the next definition is used instead. It is also used if a library annotated with a lib: comment cannot be loaded. By default next is used."))
+        .arg(Arg::with_name("sequence")
+            .required(false)
+            .long("sequence")
+            .help("Start each log line with the sequence number of the call")
+            .long_help("Counts all calls of the stubs with a single atomic counter and starts each log line with the number of the call, like #1. This shows the order of calls from different threads without the cost of timestamps. Binary logs do not include the sequence number."))
+        .arg(Arg::with_name("cxx")
+            .required(false)
+            .long("cxx")
+            .help("Generate code that can be compiled as C++")
+            .long_help("Wraps the stubs in extern \"C\", so they keep their C symbol names when compiled as C++, and compiles them with -x c++. This allows using a C++ compiler like g++ with --c-compiler. Unless --std is given, the code is compiled with -std=gnu++11."))
+        .arg(Arg::with_name("cache-dir")
+            .required(false)
+            .takes_value(true)
+            .conflicts_with("output-c")
+            .long("cache-dir")
+            .value_name("DIR")
+            .help("Reuse shared objects compiled from the same code")
+            .long_help("Stores the compiled shared objects in DIR, named after a hash of the generated C code and the compiler command. If a shared object for the same code and command is found there, it is used instead of compiling the code again. Changes of the compiler itself are not detected, so the cache needs to be cleared after updating it."))
+        .arg(Arg::with_name("no-result")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("no-result")
+            .value_name("NAME,...")
+            .help("Do not log the results of the given functions")
+            .long_help("The stubs of the given functions log only the arguments of the calls, not the results. The results are still returned as usual. Several functions can be separated by commas. Can be used multiple times."))
+        .arg(Arg::with_name("flush")
+            .required(false)
+            .long("flush")
+            .help("Flush the debug output after each log line")
+            .long_help("Calls fflush after writing each log line, so no lines are lost in a buffer if the program crashes, even if it made stderr buffered. This makes logging slower. Binary logs are not buffered."))
+        .arg(Arg::with_name("preload-lib")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .conflicts_with_all(&["output-c", "create-so"])
+            .long("preload-lib")
+            .value_name("PATH")
+            .help("Preload another library together with the stubs")
+            .long_help("Adds the shared object at PATH to LD_PRELOAD when running the command, for example for code the hooks need. The libraries are preloaded after the stubs in the given order, unless --preload-lib-first is given. Can be used multiple times."))
+        .arg(Arg::with_name("preload-lib-first")
+            .required(false)
+            .long("preload-lib-first")
+            .requires("preload-lib")
+            .help("Preload the libraries given with --preload-lib before the stubs")
+            .long_help("Puts the libraries given with --preload-lib in front of the stubs in LD_PRELOAD, so their definitions of a function are used instead of the stub."))
+        .arg(Arg::with_name("emit-script")
+            .required(false)
+            .takes_value(true)
+            .conflicts_with_all(&["list", "output-dir"])
+            .long("emit-script")
+            .value_name("FILE")
+            .help("Write a shell script compiling and preloading the C code to a file")
+            .long_help("Writes a shell script to FILE that contains the generated C code, compiles it like ldpsc would and runs a command with it preloaded. The command is given to the script as arguments, or the command given to ldpsc is run. The script does not need ldpsc, so it can be used on other machines."))
+        .arg(Arg::with_name("va-list-variant")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("va-list-variant")
+            .value_name("NAME=VNAME")
+            .validator(|value| parse_key_value(&value).map(|_| ()))
+            .help("Forwards the variable arguments of a stub to a function taking a va_list")
+            .long_help("The variable arguments of the stub for NAME are forwarded as a va_list to the function VNAME, like printf forwards them to vprintf. The variants of common functions like printf are known already. Variadic functions without a known variant are an error. Can be used multiple times."))
+}
+
+/// Parses the configuration from the given command line arguments.
+fn parse_config<I, T>(args: I) -> clap::Result<Config>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let mut args = args.into_iter().map(Into::into).collect::<Vec<OsString>>();
+    let position = args.len().min(1);
+
+    // The options from the configuration file are only used if the arguments neither set them
+    // nor conflict with them, so the arguments take precedence.
+    let mut config_args = Vec::new();
+    if let Some(config_file) = find_config_file(&args) {
+        let options = config_file::read_config_file(&config_file)
+            .map_err(|err| clap::Error::with_description(&err, clap::ErrorKind::InvalidValue))?;
 
-    Config {
+        for (name, values) in options {
+            let mut candidate = args[..position].to_vec();
+            candidate.extend(config_args.iter().chain(&values).cloned());
+            candidate.extend(args[position..].iter().cloned());
+
+            match app().get_matches_from_safe(candidate) {
+                Ok(ref matches) if matches.occurrences_of(&name) > values.len() as u64 => (),
+                Err(ref err) if err.kind == clap::ErrorKind::ArgumentConflict => (),
+                _ => config_args.extend(values),
+            }
+        }
+    }
+
+    args.splice(position..position, config_args);
+
+    let matches = app().get_matches_from_safe(args)?;
+
+    Ok(Config {
         input_file: matches.value_of("input").unwrap_or("-").to_string(),
-        output_file: matches.value_of("output-file").unwrap_or("-").to_string(),
+        output_file: matches.value_of("output").unwrap_or("-").to_string(),
         output_to_c: matches.is_present("output-c"),
         debug_output: matches.value_of("debug-output").unwrap_or("-").to_string(),
         c_compiler: matches.value_of("c-compiler").unwrap_or("cc").to_string(),
@@ -239,5 +1734,1130 @@ fn get_config() -> Config {
         command: matches
             .values_of("command")
             .map(|cmds| cmds.map(|cmd| cmd.to_string()).collect::<Vec<String>>()),
+        timeout: matches
+            .value_of("timeout")
+            .map(|secs| Duration::from_secs(secs.parse().expect("validated by clap"))),
+        env: matches
+            .values_of("env")
+            .map(|vars| {
+                vars.map(|var| parse_env_definition(var).expect("validated by clap"))
+                    .collect()
+            }).unwrap_or_default(),
+        capture: matches.is_present("capture"),
+        stdout_file: matches.value_of("stdout-file").map(|file| file.to_string()),
+        stderr_file: matches.value_of("stderr-file").map(|file| file.to_string()),
+        cc_flags: matches
+            .values_of("cc-flag")
+            .map(|flags| flags.map(|flag| flag.to_string()).collect())
+            .unwrap_or_default(),
+        verbose: matches.is_present("verbose"),
+        temp_dir: matches.value_of_os("temp-dir").map(PathBuf::from),
+        pointer_format: match matches.value_of("pointer-format") {
+            Some("hex") => PointerFormat::Hex,
+            _ => PointerFormat::Native,
+        },
+        log_point: match matches.value_of("log-point") {
+            Some("entry") => LogPoint::Entry,
+            Some("both") => LogPoint::Both,
+            _ => LogPoint::Exit,
+        },
+        bool_names: matches.is_present("bool-names"),
+        log_prefix: matches.value_of("log-prefix").unwrap_or("").to_string(),
+        strict: matches.is_present("strict"),
+        aliases: matches
+            .values_of("alias")
+            .map(|aliases| {
+                aliases
+                    .map(|alias| parse_key_value(alias).expect("validated by clap"))
+                    .collect()
+            }).unwrap_or_default(),
+        log_banner: matches.is_present("log-banner"),
+        failures: matches
+            .values_of("fail")
+            .map(|failures| {
+                failures
+                    .map(|failure| parse_failure(failure).expect("validated by clap"))
+                    .collect()
+            }).unwrap_or_default(),
+        fail_rates: matches
+            .values_of("fail-rate")
+            .map(|rates| {
+                rates
+                    .map(|rate| parse_fail_rate(rate).expect("validated by clap"))
+                    .collect()
+            }).unwrap_or_default(),
+        list: matches.is_present("list"),
+        hardened: matches.is_present("hardened"),
+        clean_env: matches.is_present("clean-env"),
+        makefile: matches.value_of("emit-makefile").map(|file| file.to_string()),
+        preprocess: matches.is_present("preprocess"),
+        lenient: matches.is_present("lenient"),
+        orig_prefix: matches.value_of("orig-prefix").unwrap_or("original_").to_string(),
+        output_dir: matches.value_of("output-dir").map(|dir| dir.to_string()),
+        name: matches.value_of("name").map(|name| name.to_string()),
+        null_marker: matches.value_of("null-marker").map(|marker| marker.to_string()),
+        list_supported_types: matches.is_present("list-supported-types"),
+        split_by_function: matches.is_present("split-by-function"),
+        self_test: matches.is_present("self-test"),
+        int_radix: match matches.value_of("int-radix") {
+            Some("hex") => IntRadix::Hex,
+            _ => IntRadix::Decimal,
+        },
+        fail_conditions: matches
+            .values_of("when")
+            .map(|conditions| {
+                conditions
+                    .map(|condition| parse_condition(condition).expect("validated by clap"))
+                    .collect()
+            }).unwrap_or_default(),
+        export_symbols: matches.is_present("export-symbols"),
+        quiet: matches.is_present("quiet"),
+        per_thread_log: matches.value_of("per-thread-log").map(|dir| dir.to_string()),
+        no_header_defines: matches.is_present("no-header-defines"),
+        log_format: match matches.value_of("log-format") {
+            Some("binary") => LogFormat::Binary,
+            _ => LogFormat::Text,
+        },
+        schema: matches.value_of("emit-schema").map(|file| file.to_string()),
+        annotate: matches.is_present("annotate"),
+        compiler_timeout: matches
+            .value_of("compiler-timeout")
+            .map(|secs| Duration::from_secs(secs.parse().expect("validated by clap"))),
+        template: matches
+            .value_of("template")
+            .map(|template| parse_template(template).expect("validated by clap")),
+        error_format: match matches.value_of("error-format") {
+            Some("json") => ErrorFormat::Json,
+            _ => ErrorFormat::Text,
+        },
+        record: matches.value_of("record").map(|file| file.to_string()),
+        c_standard: if matches.is_present("cxx") && matches.occurrences_of("std") == 0 {
+            DEFAULT_CXX_STANDARD.to_string()
+        } else {
+            matches.value_of("std").unwrap().to_string()
+        },
+        hook_header: matches.value_of("hook-header").map(absolute_path),
+        max_calls: matches
+            .value_of("max-calls")
+            .map(|calls| calls.parse().expect("validated by clap")),
+        interactive: matches.is_present("interactive"),
+        selected_functions: None,
+        hexdump_buffers: if matches.is_present("hexdump-buffers") {
+            Some(matches
+                .value_of("hexdump-cap")
+                .map_or(DEFAULT_HEXDUMP_CAP, |cap| cap.parse().expect("validated by clap")))
+        } else {
+            None
+        },
+        null_on_missing: matches.is_present("null-on-missing"),
+        canonical: matches.is_present("canonical"),
+        bits: matches
+            .value_of("bits")
+            .map(|bits| bits.parse().expect("validated by clap")),
+        deref_strings: matches.is_present("deref-strings"),
+        abort_functions: matches
+            .values_of("abort")
+            .map(|names| {
+                names
+                    .flat_map(|names| names.split(','))
+                    .filter(|name| !name.is_empty())
+                    .map(|name| name.to_string())
+                    .collect()
+            }).unwrap_or_default(),
+        backtrace: if matches.is_present("backtrace") {
+            Some(matches
+                .value_of("backtrace-depth")
+                .map_or(DEFAULT_BACKTRACE_DEPTH, |depth| depth.parse().expect("validated by clap")))
+        } else {
+            None
+        },
+        symbol_list: matches.value_of("symbol-list").map(|file| file.to_string()),
+        dlsym_handle: match matches.value_of("dlsym-handle") {
+            Some("default") => DlsymHandle::Default,
+            _ => DlsymHandle::Next,
+        },
+        sequence: matches.is_present("sequence"),
+        cxx: matches.is_present("cxx"),
+        cache_dir: matches.value_of_os("cache-dir").map(PathBuf::from),
+        no_result_functions: matches
+            .values_of("no-result")
+            .map(|names| {
+                names
+                    .flat_map(|names| names.split(','))
+                    .filter(|name| !name.is_empty())
+                    .map(|name| name.to_string())
+                    .collect()
+            }).unwrap_or_default(),
+        flush: matches.is_present("flush"),
+        preload_libs: matches
+            .values_of_os("preload-lib")
+            .map(|paths| paths.map(PathBuf::from).collect())
+            .unwrap_or_default(),
+        preload_libs_first: matches.is_present("preload-lib-first"),
+        script: matches.value_of("emit-script").map(|file| file.to_string()),
+        va_list_variants: matches
+            .values_of("va-list-variant")
+            .map(|variants| {
+                variants
+                    .map(|variant| parse_key_value(variant).expect("validated by clap"))
+                    .collect()
+            }).unwrap_or_default(),
+    })
+}
+
+/// Returns the given path relative to the current directory, if it is not absolute.
+///
+/// Paths written into the C code must not depend on the directory it is compiled in.
+fn absolute_path(path: &str) -> String {
+    match env::current_dir() {
+        Ok(dir) if Path::new(path).is_relative() => dir.join(path).to_string_lossy().into_owned(),
+        _ => path.to_string(),
+    }
+}
+
+/// Returns the configuration file to use for the given arguments.
+///
+/// This is either the file given with `--config` or the default configuration file if it exists.
+fn find_config_file(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1).take_while(|arg| *arg != "--");
+
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        } else if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    Some(PathBuf::from(config_file::DEFAULT_CONFIG_FILE)).filter(|path| path.is_file())
+}
+
+/// Splits a definition of the form `KEY=VALUE`.
+fn parse_key_value(definition: &str) -> Result<(String, String), String> {
+    let mut parts = definition.splitn(2, '=');
+    let key = parts.next().unwrap_or("");
+
+    match parts.next() {
+        Some(value) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("Expected KEY=VALUE, found {:?}", definition)),
+    }
+}
+
+/// Splits the definition of an environment variable of the form `KEY=VALUE`.
+///
+/// The key needs to be an identifier, so it can be written in shell scripts.
+fn parse_env_definition(definition: &str) -> Result<(String, String), String> {
+    let (key, value) = parse_key_value(definition)?;
+    validate_identifier(&key)?;
+    Ok((key, value))
+}
+
+/// Checks that the given text is a valid C identifier.
+fn validate_identifier(text: &str) -> Result<(), String> {
+    let valid = text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && text.chars().next().is_some_and(|c| !c.is_ascii_digit());
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Expected an identifier, found {:?}", text))
+    }
+}
+
+/// Splits a failure of the form `NAME=RETVAL:ERRNO`.
+fn parse_failure(definition: &str) -> Result<(String, Failure), String> {
+    let error = || format!("Expected NAME=RETVAL:ERRNO, found {:?}", definition);
+    let (name, failure) = parse_key_value(definition).map_err(|_| error())?;
+    let separator = failure.rfind(':').ok_or_else(error)?;
+    let errno = &failure[separator + 1..];
+
+    if errno.is_empty() || !errno.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(error());
+    }
+
+    Ok((
+        name,
+        Failure {
+            value: failure[..separator].to_string(),
+            errno: errno.to_string(),
+        },
+    ))
+}
+
+/// Splits a condition of the form `NAME:PARAM==VALUE`.
+fn parse_condition(definition: &str) -> Result<(String, Condition), String> {
+    let error = || format!("Expected NAME:PARAM==VALUE, found {:?}", definition);
+    let mut parts = definition.splitn(2, ':');
+    let name = parts.next().unwrap_or("");
+    let mut comparison = parts.next().ok_or_else(error)?.splitn(2, "==");
+    let parameter = comparison.next().unwrap_or("");
+    let value = comparison.next().ok_or_else(error)?;
+
+    if name.is_empty() || parameter.is_empty() {
+        return Err(error());
+    }
+
+    Ok((
+        name.to_string(),
+        Condition {
+            parameter: parameter.to_string(),
+            value: value.to_string(),
+        },
+    ))
+}
+
+/// Splits a failure rate of the form `NAME=PERCENT`.
+fn parse_fail_rate(definition: &str) -> Result<(String, u32), String> {
+    let error = || {
+        format!(
+            "Expected NAME=PERCENT with PERCENT from 0 to 100, found {:?}",
+            definition
+        )
+    };
+    let (name, rate) = parse_key_value(definition).map_err(|_| error())?;
+
+    match rate.parse() {
+        Ok(rate) if rate <= 100 => Ok((name, rate)),
+        _ => Err(error()),
+    }
+}
+
+/// Splits a log line template into its text and placeholders.
+fn parse_template(template: &str) -> Result<Vec<TemplatePart>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(TemplatePart::Text(rest[..start].to_string()));
+        }
+
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unterminated placeholder in template {:?}", template))?;
+
+        parts.push(match &rest[start + 1..start + end] {
+            "fn" => TemplatePart::Function,
+            "args" => TemplatePart::Arguments,
+            "ret" => TemplatePart::Result,
+            "pid" => TemplatePart::Pid,
+            "tid" => TemplatePart::Tid,
+            placeholder => Err(format!(
+                "Unknown placeholder {{{}}} in template {:?}",
+                placeholder, template
+            ))?,
+        });
+        rest = &rest[start + end + 1..];
+    }
+
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Text(rest.to_string()));
+    }
+
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        ffi::OsStr,
+        os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+    };
+
+    #[test]
+    fn test_run_command_timeout() {
+        let config = parse_config(["ldpsc", "--timeout", "1", "sleep", "10"]).unwrap();
+
+        let start = Instant::now();
+        let result = run_command(&config, "");
+
+        assert!(result.unwrap_err().message.contains("timed out after 1 seconds"));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_run_command_without_timeout() {
+        let config = parse_config(["ldpsc", "--timeout", "10", "true"]).unwrap();
+        assert!(run_command(&config, "").is_ok());
+
+        let config = parse_config(["ldpsc", "false"]).unwrap();
+        assert!(
+            run_command(&config, "")
+                .unwrap_err()
+                .message
+                .contains("finished unsuccessfully with exit code 1")
+        );
+    }
+
+    #[test]
+    fn test_run_command_exit_code() {
+        let config = parse_config(["ldpsc", "--", "sh", "-c", "exit 42"]).unwrap();
+        let err = run_command(&config, "").unwrap_err();
+
+        assert_eq!(err.exit_code, 42);
+        assert!(err.message.contains("finished unsuccessfully with exit code 42"));
+
+        let config = parse_config(["ldpsc", "--", "sh", "-c", "kill -9 $$"]).unwrap();
+        assert_eq!(run_command(&config, "").unwrap_err().exit_code, 128 + 9);
+    }
+
+    #[test]
+    fn test_run_command_env() {
+        let config = parse_config([
+            "ldpsc",
+            "--env",
+            "FIRST=1",
+            "--env",
+            "SECOND=a=b",
+            "--",
+            "sh",
+            "-c",
+            "test \"$FIRST\" = 1 && test \"$SECOND\" = a=b",
+        ]).unwrap();
+
+        assert_eq!(
+            config.env,
+            vec![
+                ("FIRST".to_string(), "1".to_string()),
+                ("SECOND".to_string(), "a=b".to_string())
+            ]
+        );
+        assert!(run_command(&config, "").is_ok());
+    }
+
+    #[test]
+    fn test_clean_env() {
+        let parent_env = || {
+            vec![
+                ("LDPSC_TEST_CLEAN_ENV".into(), "1".into()),
+                ("PATH".into(), env::var_os("PATH").unwrap()),
+            ]
+        };
+        let succeeds = |config: &Config| {
+            let mut command = build_command(config, "", parent_env()).unwrap();
+            execute_command(config, &mut command).unwrap().0.success()
+        };
+
+        let config = parse_config([
+            "ldpsc",
+            "--env",
+            "SET=1",
+            "--clean-env",
+            "--",
+            "sh",
+            "-c",
+            "test -z \"$LDPSC_TEST_CLEAN_ENV\" && test \"$SET\" = 1",
+        ]).unwrap();
+        assert!(succeeds(&config));
+
+        let config = parse_config([
+            "ldpsc",
+            "--",
+            "sh",
+            "-c",
+            "test \"$LDPSC_TEST_CLEAN_ENV\" = 1",
+        ]).unwrap();
+        assert!(succeeds(&config));
+    }
+
+    #[test]
+    fn test_capture_output() {
+        let config = parse_config(["ldpsc", "--capture", "echo", "hello world"]).unwrap();
+        let mut command = build_command(&config, "", env::vars_os()).unwrap();
+
+        let (status, captured) = execute_command(&config, &mut command).unwrap();
+        let captured = captured.unwrap();
+
+        assert!(status.success());
+        assert_eq!(captured.stdout, b"hello world\n");
+        assert_eq!(captured.stderr, b"");
+    }
+
+    #[test]
+    fn test_absolute_preload_path() {
+        let config = parse_config(["ldpsc", "true"]).unwrap();
+        let preload_path = |command: &Command| {
+            command
+                .get_envs()
+                .find(|(key, _)| *key == "LD_PRELOAD")
+                .and_then(|(_, value)| value)
+                .map(PathBuf::from)
+        };
+
+        let command = build_command(&config, "out/stubs.so", env::vars_os()).unwrap();
+        assert_eq!(
+            preload_path(&command),
+            Some(env::current_dir().unwrap().join("out/stubs.so"))
+        );
+
+        let command = build_command(&config, "/tmp/stubs.so", env::vars_os()).unwrap();
+        assert_eq!(preload_path(&command), Some(PathBuf::from("/tmp/stubs.so")));
+    }
+
+    #[test]
+    fn test_preload_lib() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let first = dir.path().join("first.so");
+        let second = dir.path().join("second.so");
+        File::create(&first).unwrap();
+        File::create(&second).unwrap();
+
+        let preload_list = |args: &[&str]| {
+            let config = parse_config(
+                ["ldpsc", "--preload-lib", first.to_str().unwrap()]
+                    .iter()
+                    .chain(&["--preload-lib", second.to_str().unwrap()])
+                    .chain(args)
+                    .chain(&["true"]),
+            ).unwrap();
+            build_command(&config, "/tmp/stubs.so", env::vars_os()).map(|command| {
+                command
+                    .get_envs()
+                    .find(|(key, _)| *key == "LD_PRELOAD")
+                    .and_then(|(_, value)| value)
+                    .map(|value| value.to_str().unwrap().to_string())
+            })
+        };
+
+        assert_eq!(
+            preload_list(&[]),
+            Ok(Some(format!("/tmp/stubs.so:{}:{}", first.display(), second.display())))
+        );
+        assert_eq!(
+            preload_list(&["--preload-lib-first"]),
+            Ok(Some(format!("{}:{}:/tmp/stubs.so", first.display(), second.display())))
+        );
+
+        fs::remove_file(&second).unwrap();
+        assert!(preload_list(&[]).is_err());
+        assert!(parse_config(["ldpsc", "--preload-lib-first", "true"]).is_err());
+    }
+
+    #[test]
+    fn test_uncaptured_output() {
+        let config = parse_config(["ldpsc", "true"]).unwrap();
+        let mut command = build_command(&config, "", env::vars_os()).unwrap();
+
+        let (_, captured) = execute_command(&config, &mut command).unwrap();
+
+        assert!(captured.is_none());
+    }
+
+    #[test]
+    fn test_config_file() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let path = dir.path().join("ldpsc.toml");
+        write_file(
+            path.to_str().unwrap(),
+            b"c-compiler = \"clang\"\ndebug-output = \"log.txt\"\ncc-flag = [\"-O2\"]\n",
+        ).unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = parse_config(["ldpsc", "--config", path, "-s"]).unwrap();
+        assert_eq!(config.c_compiler, "clang");
+        assert_eq!(config.debug_output, "log.txt");
+        assert_eq!(config.cc_flags, vec!["-O2".to_string()]);
+
+        let config = parse_config(["ldpsc", "-C", "gcc", "-s", "--config", path]).unwrap();
+        assert_eq!(config.c_compiler, "gcc");
+        assert_eq!(config.debug_output, "log.txt");
+
+        let config = parse_config(["ldpsc", "--config", path, "-s", "--cc-flag", "-g"]).unwrap();
+        assert_eq!(config.cc_flags, vec!["-g".to_string()]);
+
+        // Options conflicting with the arguments are not used.
+        write_file(
+            path,
+            b"quiet = true\noutput-c = true\nc-compiler = \"clang\"\n",
+        ).unwrap();
+        let config = parse_config(["ldpsc", "--config", path, "--verbose", "-s"]).unwrap();
+        assert!(config.verbose);
+        assert!(!config.quiet);
+        assert!(config.create_shared_object);
+        assert!(!config.output_to_c);
+        assert_eq!(config.c_compiler, "clang");
+
+        let config = parse_config(["ldpsc", "--config", path]).unwrap();
+        assert!(config.quiet);
+        assert!(config.output_to_c);
+
+        let config = parse_config(["ldpsc", "-s"]).unwrap();
+        assert_eq!(config.c_compiler, "cc");
+        assert_eq!(config.debug_output, "-");
+        assert!(config.cc_flags.is_empty());
+    }
+
+    #[test]
+    fn test_missing_config_file() {
+        assert!(parse_config(["ldpsc", "--config", "/nonexistent/ldpsc.toml", "-s"]).is_err());
+    }
+
+    #[test]
+    fn test_temp_dir() {
+        let parent = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let config =
+            parse_config(["ldpsc", "-s", "--temp-dir", parent.path().to_str().unwrap()]).unwrap();
+
+        let tmp_dir = create_temp_dir(&config).unwrap();
+        assert_eq!(tmp_dir.path().parent(), Some(parent.path()));
+
+        let config = parse_config(["ldpsc", "-s", "--temp-dir", "/nonexistent/ldpsc"]).unwrap();
+        assert!(create_temp_dir(&config).is_err());
+
+        // Paths that are not valid UTF-8 are passed on as they are.
+        let input = parent.path().join("stubs.h");
+        fs::write(&input, "int puts(const char *s);").unwrap();
+        let parent = parent.path().join(OsStr::from_bytes(b"non-utf8-\xff"));
+        fs::create_dir(&parent).unwrap();
+        let config = parse_config(vec![
+            OsString::from("ldpsc"),
+            OsString::from("-i"),
+            input.into_os_string(),
+            OsString::from("--temp-dir"),
+            parent.clone().into_os_string(),
+            OsString::from("true"),
+        ]).unwrap();
+        assert!(run(&config).is_ok());
+        assert_eq!(fs::read_dir(&parent).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_malformed_env() {
+        assert!(parse_config(["ldpsc", "--env", "NOVALUE", "true"]).is_err());
+        assert!(parse_config(["ldpsc", "--env", "=value", "true"]).is_err());
+        assert!(parse_key_value("NOVALUE").unwrap_err().contains("Expected KEY=VALUE"));
+        assert!(parse_config(["ldpsc", "--env", "A B=x", "true"]).is_err());
+        assert!(parse_config(["ldpsc", "--env", "1A=x", "true"]).is_err());
+    }
+
+    /// Returns the arguments the compiler is run with for the given configuration.
+    fn cc_args(config: &Config) -> Vec<String> {
+        build_cc_command(config, Path::new("output.c"), Path::new("output.so"))
+            .get_args()
+            .map(|arg| arg.to_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_hardened() {
+        let config = parse_config(["ldpsc", "--hardened", "--cc-flag=-O2", "true"]).unwrap();
+        assert_eq!(
+            cc_args(&config),
+            [
+                "output.c",
+                "-o",
+                "output.so",
+                "-shared",
+                "-fPIC",
+                "-ldl",
+                "-std=gnu11",
+                "-z",
+                "now",
+                "-z",
+                "relro",
+                "-fstack-protector-strong",
+                "-O2"
+            ]
+        );
+
+        let config = parse_config(["ldpsc", "true"]).unwrap();
+        assert_eq!(
+            cc_args(&config),
+            ["output.c", "-o", "output.so", "-shared", "-fPIC", "-ldl", "-std=gnu11"]
+        );
+    }
+
+    #[test]
+    fn test_std() {
+        let config = parse_config(["ldpsc", "--std", "gnu99", "true"]).unwrap();
+        assert_eq!(
+            cc_args(&config),
+            ["output.c", "-o", "output.so", "-shared", "-fPIC", "-ldl", "-std=gnu99"]
+        );
+    }
+
+    #[test]
+    fn test_bits() {
+        let config = parse_config(["ldpsc", "--bits", "32", "true"]).unwrap();
+        assert_eq!(
+            cc_args(&config),
+            ["output.c", "-o", "output.so", "-shared", "-fPIC", "-ldl", "-std=gnu11", "-m32"]
+        );
+        assert!(parse_config(["ldpsc", "--bits", "16", "true"]).is_err());
+    }
+
+    #[test]
+    fn test_backtrace_flags() {
+        let config = parse_config(["ldpsc", "--backtrace", "true"]).unwrap();
+        assert!(cc_args(&config).contains(&"-rdynamic".to_string()));
+    }
+
+    #[test]
+    fn test_no_header_defines() {
+        let config = parse_config(["ldpsc", "--no-header-defines", "true"]).unwrap();
+        assert_eq!(
+            cc_args(&config),
+            [
+                "output.c",
+                "-o",
+                "output.so",
+                "-shared",
+                "-fPIC",
+                "-ldl",
+                "-std=gnu11",
+                "-D_GNU_SOURCE"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cxx() {
+        let config = parse_config(["ldpsc", "--cxx", "true"]).unwrap();
+        assert_eq!(
+            cc_args(&config),
+            [
+                "-x",
+                "c++",
+                "output.c",
+                "-o",
+                "output.so",
+                "-shared",
+                "-fPIC",
+                "-ldl",
+                "-std=gnu++11"
+            ]
+        );
+
+        let config = parse_config(["ldpsc", "--cxx", "--std", "c++17", "true"]).unwrap();
+        assert!(cc_args(&config).contains(&"-std=c++17".to_string()));
+
+        // The generated code compiles as C++, even for types written differently in C.
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let input = dir.path().join("stubs.h");
+        let output = dir.path().join("stubs.so");
+        File::create(&input)
+            .unwrap()
+            .write_all(
+                b"char *stpcpy(char *restrict dest, const char src[restrict static 1]);\n\
+                  int printf(const char *restrict format, ...);\n\
+                  void *memset(void s[const], int c, size_t n);\n",
+            ).unwrap();
+        let config = parse_config([
+            "ldpsc",
+            "--cxx",
+            "-s",
+            "-i",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ]).unwrap();
+        assert!(run(&config).is_ok());
+        assert!(output.is_file());
+    }
+
+    #[test]
+    fn test_preprocess() {
+        let config =
+            parse_config(["ldpsc", "-c", "--preprocess", "--cc-flag=-DNAME=name"]).unwrap();
+        let mut output = Vec::new();
+        let mut diagnostics = Diagnostics::new(&mut output, &config);
+
+        let preprocessed = preprocess(
+            &config,
+            b"#define STRING const char *\nSTRING NAME(STRING s);\n".to_vec(),
+            &mut diagnostics,
+        ).unwrap();
+        let transformed =
+            c_parser::transform_file(&preprocessed, &config, &mut diagnostics).unwrap();
+        assert!(transformed.contains("const char *name(const char *s) {\n"));
+
+        assert!(preprocess(&config, b"#error failure\n".to_vec(), &mut diagnostics).is_err());
+    }
+
+    #[test]
+    fn test_output_dir() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let input = dir.path().join("stubs.h");
+        let output_dir = dir.path().join("out");
+        File::create(&input)
+            .unwrap()
+            .write_all(b"int puts(const char *s);")
+            .unwrap();
+
+        let config = parse_config([
+            "ldpsc",
+            "-i",
+            input.to_str().unwrap(),
+            "--output-dir",
+            output_dir.to_str().unwrap(),
+        ]).unwrap();
+        assert!(run(&config).is_ok());
+        assert!(output_dir.join("stubs.c").is_file());
+        assert!(output_dir.join("stubs.so").is_file());
+
+        let config = parse_config([
+            "ldpsc",
+            "-i",
+            input.to_str().unwrap(),
+            "--output-dir",
+            output_dir.to_str().unwrap(),
+            "--name",
+            "other",
+        ]).unwrap();
+        assert!(run(&config).is_ok());
+        assert!(output_dir.join("other.c").is_file());
+        assert!(output_dir.join("other.so").is_file());
+    }
+
+    #[test]
+    fn test_artifact_name() {
+        let config = parse_config(["ldpsc", "--output-dir", "out", "--name", "a"]).unwrap();
+        assert_eq!(artifact_name(&config), "a");
+        let config = parse_config(["ldpsc", "--output-dir", "out", "--name", "b"]).unwrap();
+        assert_eq!(artifact_name(&config), "b");
+
+        let config = parse_config(["ldpsc", "--output-dir", "out", "--name", "../my stubs"]).unwrap();
+        assert_eq!(artifact_name(&config), "_._my_stubs");
+
+        let config = parse_config(["ldpsc", "--output-dir", "out", "-i", "dir/my stubs.h"]).unwrap();
+        assert_eq!(artifact_name(&config), "my_stubs");
+
+        let config = parse_config(["ldpsc", "--output-dir", "out"]).unwrap();
+        assert_eq!(artifact_name(&config), format!("output-{}", process::id()));
+    }
+
+    #[test]
+    fn test_split_by_function() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let input = dir.path().join("stubs.h");
+        File::create(&input)
+            .unwrap()
+            .write_all(b"int puts(const char *s);\nvoid *malloc(size_t size);")
+            .unwrap();
+
+        let config = parse_config([
+            "ldpsc",
+            "-i",
+            input.to_str().unwrap(),
+            "--output-dir",
+            dir.path().to_str().unwrap(),
+            "--split-by-function",
+        ]).unwrap();
+        assert!(run(&config).is_ok());
+        assert!(dir.path().join("stubs-puts.so").is_file());
+        assert!(dir.path().join("stubs-malloc.so").is_file());
+        assert!(!dir.path().join("stubs.so").exists());
+
+        let mut puts = String::new();
+        File::open(dir.path().join("stubs-puts.c"))
+            .unwrap()
+            .read_to_string(&mut puts)
+            .unwrap();
+        assert!(puts.starts_with("#define _GNU_SOURCE\n"));
+        assert!(!puts.contains("malloc"));
+    }
+
+    #[test]
+    fn test_self_test() {
+        let config = parse_config(["ldpsc", "--self-test"]).unwrap();
+        assert!(run(&config).is_ok());
+
+        let config = parse_config(["ldpsc", "--self-test", "-C", "/nonexistent/cc"]).unwrap();
+        assert!(run(&config).is_err());
+    }
+
+    #[test]
+    fn test_read_file_errors() {
+        struct FailingReader;
+
+        impl Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken pipe"))
+            }
+        }
+
+        let err = read_file("does/not/exist.h").unwrap_err();
+        assert!(err.starts_with("does/not/exist.h: "));
+        assert_eq!(
+            read_labeled(FailingReader, "stdin"),
+            Err("stdin: broken pipe".to_string())
+        );
+        assert_eq!(read_labeled(&b"int f(int x);"[..], "stdin"), Ok(b"int f(int x);".to_vec()));
+    }
+
+    #[test]
+    fn test_compiler_timeout() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let compiler = dir.path().join("slow-cc");
+        fs::write(&compiler, "#!/bin/sh\nsleep 10\n").unwrap();
+        fs::set_permissions(&compiler, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = parse_config([
+            "ldpsc",
+            "--compiler-timeout",
+            "1",
+            "-C",
+            compiler.to_str().unwrap(),
+            "true",
+        ]).unwrap();
+        let mut output = Vec::new();
+        let start = Instant::now();
+        assert_eq!(
+            run_cc(
+                &config,
+                &dir.path().join("output.c"),
+                &dir.path().join("output.so"),
+                &mut Diagnostics::new(&mut output, &config)
+            ),
+            Err("The compiler timed out after 1 seconds".to_string())
+        );
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_json_errors() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let input = dir.path().join("input.h");
+        fs::write(&input, "int puts(const char *s);\n  int foo(int x)\n").unwrap();
+
+        let config = parse_config([
+            "ldpsc",
+            "--error-format",
+            "json",
+            "-i",
+            input.to_str().unwrap(),
+            "true",
+        ]).unwrap();
+        assert_eq!(config.error_format, ErrorFormat::Json);
+        assert_eq!(
+            run(&config).unwrap_err().to_json(),
+            "{\"stage\":\"parse\",\
+             \"message\":\"Expected ';' after function declaration of 'foo'\",\
+             \"line\":2,\"column\":3}"
+        );
+
+        let err = Error::from("\"quoted\"\tpath\\\n".to_string()).in_stage(Stage::Run);
+        assert_eq!(
+            err.to_json(),
+            "{\"stage\":\"run\",\"message\":\"\\\"quoted\\\"\\tpath\\\\\\n\"}"
+        );
+    }
+
+    #[test]
+    fn test_interactive() {
+        let names = ["puts".to_string(), "malloc".to_string(), "free".to_string()];
+        let mut prompts = Vec::new();
+        let selected = prompt_selection(&names, &b"y\nn\n"[..], &mut prompts).unwrap();
+        assert_eq!(selected, ["puts", "free"]);
+        assert_eq!(
+            String::from_utf8(prompts).unwrap(),
+            "stub 'puts'? [Y/n] stub 'malloc'? [Y/n] stub 'free'? [Y/n] "
+        );
+
+        let config = Config {
+            selected_functions: Some(selected),
+            ..parse_config(["ldpsc", "-c"]).unwrap()
+        };
+        let mut output = Vec::new();
+        let transformed = c_parser::transform_file(
+            b"int puts(const char *s);\nvoid *malloc(size_t size);\nvoid free(void *ptr);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).unwrap();
+        assert!(transformed.contains("int puts(const char *s) {\n"));
+        assert!(transformed.contains("void free(void *ptr) {\n"));
+        assert!(!transformed.contains("malloc("));
+
+        let config = Config {
+            selected_functions: Some(vec![]),
+            ..config
+        };
+        assert!(c_parser::transform_file(
+            b"int puts(const char *s);",
+            &config,
+            &mut Diagnostics::new(&mut output, &config),
+        ).is_err());
+    }
+
+    #[test]
+    fn test_stub_count() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let input = dir.path().join("stubs.h");
+        let output = dir.path().join("stubs.c");
+        File::create(&input)
+            .unwrap()
+            .write_all(b"int puts(const char *s);\nvoid *malloc(size_t size);\nint close(int fd);")
+            .unwrap();
+
+        let args = [
+            "ldpsc",
+            "-c",
+            "-i",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ];
+        let config = parse_config(args).unwrap();
+        let mut messages = Vec::new();
+        run_with_diagnostics(&config, &mut Diagnostics::new(&mut messages, &config)).unwrap();
+        assert_eq!(messages, b"ldpsc: generated 3 stub(s)\n");
+
+        let config = parse_config(args.iter().chain(&["--quiet"])).unwrap();
+        let mut messages = Vec::new();
+        run_with_diagnostics(&config, &mut Diagnostics::new(&mut messages, &config)).unwrap();
+        assert_eq!(messages, b"");
+    }
+
+    #[test]
+    fn test_symbol_list() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let input = dir.path().join("stubs.h");
+        let output = dir.path().join("stubs.c");
+        let symbol_list = dir.path().join("symbols");
+        File::create(&input)
+            .unwrap()
+            .write_all(b"int puts(const char *s);\nint close(int fd);\nint puts(const char *s);")
+            .unwrap();
+
+        let config = parse_config([
+            "ldpsc",
+            "-c",
+            "-i",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--symbol-list",
+            symbol_list.to_str().unwrap(),
+        ]).unwrap();
+        assert!(run(&config).is_ok());
+        assert_eq!(fs::read(&symbol_list).unwrap(), b"puts\nclose\n");
+    }
+
+    #[test]
+    fn test_cache_dir() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let input = dir.path().join("stubs.h");
+        let output = dir.path().join("stubs.so");
+        let cache_dir = dir.path().join("cache");
+        File::create(&input)
+            .unwrap()
+            .write_all(b"int puts(const char *s);")
+            .unwrap();
+
+        let args = [
+            "ldpsc",
+            "-s",
+            "-i",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--cache-dir",
+            cache_dir.to_str().unwrap(),
+        ];
+        let config = parse_config(args).unwrap();
+        assert!(run(&config).is_ok());
+        let cached: Vec<_> = fs::read_dir(&cache_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(fs::read(&cached[0]).unwrap(), fs::read(&output).unwrap());
+
+        // The second run uses the cached shared object instead of compiling the code.
+        fs::write(&cached[0], b"marker").unwrap();
+        assert!(run(&config).is_ok());
+        assert_eq!(fs::read(&output).unwrap(), b"marker");
+
+        // Different compiler flags result in a different shared object.
+        let config = parse_config(args.iter().chain(&["--cc-flag=-O2"])).unwrap();
+        assert!(run(&config).is_ok());
+        assert_ne!(fs::read(&output).unwrap(), b"marker");
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_emit_script() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let input = dir.path().join("stubs.h");
+        let output = dir.path().join("stubs.c");
+        let script = dir.path().join("stubs.sh");
+        File::create(&input)
+            .unwrap()
+            .write_all(b"int puts(const char *s);")
+            .unwrap();
+
+        let config = parse_config([
+            "ldpsc",
+            "-c",
+            "-i",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--emit-script",
+            script.to_str().unwrap(),
+        ]).unwrap();
+        assert!(run(&config).is_ok());
+
+        let code = fs::read_to_string(&output).unwrap();
+        let script = fs::read_to_string(&script).unwrap();
+        assert!(script.contains(&format!("<<'LDPSC_EOF'\n{}LDPSC_EOF\n", code)));
+        assert!(script.contains("cc \"$dir/stubs.c\" -o \"$dir/stubs.so\" -shared -fPIC -ldl"));
+    }
+
+    #[test]
+    fn test_variadic_without_va_list_variant() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let input = dir.path().join("stubs.h");
+        File::create(&input)
+            .unwrap()
+            .write_all(b"int open(const char *pathname, int flags, ...);")
+            .unwrap();
+
+        let config = parse_config(["ldpsc", "-i", input.to_str().unwrap(), "true"]).unwrap();
+        assert!(
+            run(&config)
+                .unwrap_err()
+                .message
+                .contains("'open' has no known va_list variant")
+        );
+    }
+
+    #[test]
+    fn test_dlsym_handle_default() {
+        let dir = Builder::new().prefix("ldpsc-test").tempdir().unwrap();
+        let input = dir.path().join("stubs.h");
+        let program = dir.path().join("program");
+        File::create(&input)
+            .unwrap()
+            .write_all(b"int puts(const char *s);")
+            .unwrap();
+        File::create(dir.path().join("program.c"))
+            .unwrap()
+            .write_all(b"int puts(const char *s);\nint main(void) { return puts(\"\") < 0; }\n")
+            .unwrap();
+        assert!(
+            Command::new("cc")
+                .arg(dir.path().join("program.c"))
+                .arg("-o")
+                .arg(&program)
+                .status()
+                .unwrap()
+                .success()
+        );
+
+        // The preloaded stub finds itself with RTLD_DEFAULT and must not call itself.
+        let config = parse_config([
+            "ldpsc",
+            "-i",
+            input.to_str().unwrap(),
+            "--dlsym-handle",
+            "default",
+            "-d",
+            dir.path().join("log").to_str().unwrap(),
+            "--",
+            program.to_str().unwrap(),
+        ]).unwrap();
+        assert!(run(&config).is_ok());
     }
 }
@@ -0,0 +1,114 @@
+//! This module generates Makefiles that compile the generated C code without ldpsc.
+
+use super::{cc_language_flags, extra_cc_flags, script::quote, Config, REQUIRED_CC_FLAGS};
+use std::path::Path;
+
+/// Generates a Makefile at `makefile_path` compiling `c_path` like ldpsc would.
+///
+/// The compiler and the additional flags can be changed with the `CC` and `CFLAGS` variables.
+pub fn generate(config: &Config, c_path: &Path, makefile_path: &Path) -> Result<String, String> {
+    // Paths in the Makefile are relative to the directory make is run in.
+    let c_path = if c_path.parent() == makefile_path.parent() {
+        c_path.file_name().map(Path::new).unwrap_or(c_path)
+    } else {
+        c_path
+    };
+    let so_path = c_path.with_extension("so");
+
+    let mut language_flags = String::new();
+    for flag in cc_language_flags(config) {
+        language_flags.push_str(flag);
+        language_flags.push(' ');
+    }
+
+    // The compiler and the flags are passed to the shell as they are, so they are quoted for it.
+    let extra_flags: Vec<_> = extra_cc_flags(config)
+        .iter()
+        .map(|flag| escape(&quote(flag)))
+        .collect();
+
+    // The paths are also targets, so the recipe quotes them instead.
+    Ok(format!(
+        "CC = {}\n\
+         CFLAGS = {}\n\
+         \n\
+         all: {so}\n\
+         \n\
+         {so}: {c}\n\
+         \t$(CC) {}'$<' -o '$@' {} $(CFLAGS)\n\
+         \n\
+         .PHONY: all\n",
+        escape(&quote(&config.c_compiler)),
+        extra_flags.join(" "),
+        language_flags,
+        REQUIRED_CC_FLAGS.join(" "),
+        so = escape_path(&so_path)?,
+        c = escape_path(c_path)?,
+    ))
+}
+
+/// Escapes the characters that make treats specially in the given text.
+fn escape(text: &str) -> String {
+    text.replace('$', "$$").replace('#', "\\#")
+}
+
+/// Escapes the given path for make.
+///
+/// make splits targets at whitespace and the recipe quotes them with single quotes, so neither
+/// can be escaped.
+fn escape_path(path: &Path) -> Result<String, String> {
+    let path = path.to_string_lossy();
+
+    if path.contains(|c: char| c.is_whitespace() || c == '\'') {
+        Err(format!(
+            "{}: Paths in a Makefile cannot contain whitespace or single quotes",
+            path
+        ))
+    } else {
+        Ok(escape(&path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_config;
+
+    #[test]
+    fn test_generate() {
+        let config = parse_config(["ldpsc", "-c", "--cc-flag=-O2", "--hardened"]).unwrap();
+        assert_eq!(
+            generate(&config, Path::new("out/stubs.c"), Path::new("out/Makefile")).unwrap(),
+            "CC = cc\n\
+             CFLAGS = -std=gnu11 -z now -z relro -fstack-protector-strong -O2\n\
+             \n\
+             all: stubs.so\n\
+             \n\
+             stubs.so: stubs.c\n\
+             \t$(CC) '$<' -o '$@' -shared -fPIC -ldl $(CFLAGS)\n\
+             \n\
+             .PHONY: all\n"
+        );
+
+        let config = parse_config(["ldpsc", "-c", "--c-compiler", "clang"]).unwrap();
+        let makefile =
+            generate(&config, Path::new("stubs.c"), Path::new("build/Makefile")).unwrap();
+        assert!(makefile.starts_with("CC = clang\nCFLAGS = -std=gnu11\n"));
+        assert!(makefile.contains("stubs.so: stubs.c\n"));
+
+        let config = parse_config(["ldpsc", "-c", "--cxx"]).unwrap();
+        let makefile = generate(&config, Path::new("stubs.c"), Path::new("Makefile")).unwrap();
+        assert!(makefile.starts_with("CC = cc\nCFLAGS = -std=gnu++11\n"));
+        assert!(makefile.contains("\t$(CC) -x c++ '$<' -o '$@' -shared -fPIC -ldl $(CFLAGS)\n"));
+
+        // Characters make treats specially are escaped.
+        let config =
+            parse_config(["ldpsc", "-c", "-C", "/opt/my cc#1/cc", "--cc-flag=-DA=$B"]).unwrap();
+        let makefile = generate(&config, Path::new("a$b#c.c"), Path::new("Makefile")).unwrap();
+        assert!(makefile.starts_with("CC = '/opt/my cc\\#1/cc'\nCFLAGS = -std=gnu11 '-DA=$$B'\n"));
+        assert!(makefile.contains("a$$b\\#c.so: a$$b\\#c.c\n"));
+
+        assert!(generate(&config, Path::new("a b.c"), Path::new("Makefile")).is_err());
+        assert!(generate(&config, Path::new("it's.c"), Path::new("Makefile")).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+//! This module generates shell scripts that compile the generated C code and run a command with it.
+
+use super::{cc_language_flags, command_env, extra_cc_flags, Config, REQUIRED_CC_FLAGS};
+use std::path::Path;
+
+/// The delimiter of the here-document containing the C code, unless the code contains it.
+const HEREDOC_DELIMITER: &str = "LDPSC_EOF";
+
+/// The path the environment of the command is built with, which is replaced by the stubs.
+const STUBS_PLACEHOLDER: &str = "/ldpsc-script-stubs.so";
+
+/// Generates a shell script that compiles `code` like ldpsc would and preloads it.
+///
+/// The script runs the command given to it as arguments, or the command given to ldpsc if there
+/// are none. The files are created in a temporary directory that is removed afterwards.
+///
+/// The command gets the same environment it would get when run by ldpsc.
+pub fn generate(config: &Config, code: &str) -> Result<String, String> {
+    let mut delimiter = HEREDOC_DELIMITER.to_string();
+    while code.lines().any(|line| line == delimiter) {
+        delimiter.push('_');
+    }
+
+    let mut cc_command = vec![quote(&config.c_compiler)];
+    cc_command.extend(cc_language_flags(config).iter().map(|flag| quote(flag)));
+    cc_command.push("\"$dir/stubs.c\"".to_string());
+    cc_command.push("-o".to_string());
+    cc_command.push("\"$dir/stubs.so\"".to_string());
+    cc_command.extend(REQUIRED_CC_FLAGS.iter().map(|flag| quote(flag)));
+    cc_command.extend(extra_cc_flags(config).iter().map(|flag| quote(flag)));
+
+    let mut script = String::from(
+        "#!/bin/sh\n\
+         # Compiles the stubs generated by ldpsc and runs a command with them preloaded.\n\
+         # Usage: $0 [COMMAND [ARGS]...]\n\
+         set -e\n\
+         \n",
+    );
+
+    if let Some(command) = &config.command {
+        let command: Vec<_> = command.iter().map(|arg| quote(arg)).collect();
+        script.push_str(&format!(
+            "if [ $# -eq 0 ]; then\n    set -- {}\nfi\n\n",
+            command.join(" ")
+        ));
+    }
+
+    script.push_str(
+        "dir=$(mktemp -d)\n\
+         trap 'rm -rf \"$dir\"' EXIT\n\
+         \n",
+    );
+    script.push_str(&format!("cat > \"$dir/stubs.c\" <<'{}'\n", delimiter));
+    script.push_str(code);
+    if !code.ends_with('\n') {
+        script.push('\n');
+    }
+    script.push_str(&format!("{}\n\n", delimiter));
+    script.push_str(&cc_command.join(" "));
+    script.push_str("\n\n");
+
+    // Like ldpsc, the command only keeps the PATH it is run with from the environment.
+    if config.clean_env {
+        script.push_str("env -i PATH=\"$PATH\" ");
+    }
+
+    for (key, value) in command_env(config, Path::new(STUBS_PLACEHOLDER), Vec::new())? {
+        let value: Vec<_> = value
+            .to_string_lossy()
+            .split(STUBS_PLACEHOLDER)
+            .map(|part| if part.is_empty() { String::new() } else { quote(part) })
+            .collect();
+        script.push_str(&format!(
+            "{}={} ",
+            key.to_string_lossy(),
+            value.join("\"$dir/stubs.so\"")
+        ));
+    }
+    script.push_str("\"$@\"\n");
+
+    Ok(script)
+}
+
+/// Quotes the given string for the shell, if necessary.
+pub fn quote(value: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_=+./,:@%".contains(c);
+
+    if !value.is_empty() && value.chars().all(is_safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_config;
+
+    #[test]
+    fn test_generate() {
+        let config =
+            parse_config(["ldpsc", "--hardened", "--env", "A=b c", "--", "ls", "-l"]).unwrap();
+        let script = generate(&config, "int x;\n").unwrap();
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains("if [ $# -eq 0 ]; then\n    set -- ls -l\nfi\n"));
+        assert!(script.contains("cat > \"$dir/stubs.c\" <<'LDPSC_EOF'\nint x;\nLDPSC_EOF\n"));
+        assert!(script.contains(
+            "cc \"$dir/stubs.c\" -o \"$dir/stubs.so\" -shared -fPIC -ldl -std=gnu11 \
+             -z now -z relro -fstack-protector-strong\n"
+        ));
+        assert!(script.ends_with("A='b c' LD_PRELOAD=\"$dir/stubs.so\" \"$@\"\n"));
+
+        let config = parse_config(["ldpsc", "-c", "--cxx"]).unwrap();
+        let script = generate(&config, "LDPSC_EOF\nchar *s = \"$HOME 'a'\";").unwrap();
+        assert!(!script.contains("set --"));
+        assert!(script.contains(
+            "<<'LDPSC_EOF_'\nLDPSC_EOF\nchar *s = \"$HOME 'a'\";\nLDPSC_EOF_\n"
+        ));
+        assert!(script.contains("cc -x c++ \"$dir/stubs.c\""));
+
+        // The command gets the same environment as when it is run by ldpsc.
+        let config = parse_config([
+            "ldpsc",
+            "--clean-env",
+            "--preload-lib",
+            "/bin/sh",
+            "--preload-lib",
+            "/bin/true",
+            "--preload-lib-first",
+            "true",
+        ]).unwrap();
+        let script = generate(&config, "").unwrap();
+        assert!(script.ends_with(
+            "\nenv -i PATH=\"$PATH\" LD_PRELOAD=/bin/sh:/bin/true:\"$dir/stubs.so\" \"$@\"\n"
+        ));
+
+        let config = parse_config(["ldpsc", "--preload-lib", "/nonexistent.so", "true"]).unwrap();
+        assert!(generate(&config, "").is_err());
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("-O2"), "-O2");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(quote("$HOME"), "'$HOME'");
+    }
+}